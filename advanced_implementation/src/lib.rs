@@ -0,0 +1,38 @@
+//  library side of the tree clone :
+//  -   treeconfig : builder-style configuration of walker and printer
+//  -   treefile   : in-memory model of the tree, and the walker building it
+//  -   treelibs   : printer of the model
+//  -   treejson   : JSON printer of the model, for -J
+//  -   treexml    : XML printer of the model, for -X
+//  -   treehtml   : HTML printer of the model, for -H
+//  -   treepattern: wildcard patterns for -P and -I
+//  -   treeignore : .gitignore rules for --gitignore
+//  -   treepool   : directories read ahead by a pool of threads, for --threads
+//  -   treeusers  : names of users and groups, for -u and -g
+//  -   treetime   : dates of the files, for -D
+//  -   treedu     : disk usage of the directories, for --du
+//  -   treecolors : LS_COLORS and TREE_COLORS, for -c
+
+mod treecolors;
+pub mod treeconfig;
+mod treedu;
+pub mod treefile;
+mod treehtml;
+mod treeignore;
+mod treejson;
+pub mod treelibs;
+pub mod treepattern;
+mod treepool;
+mod treetime;
+mod treeusers;
+mod treexml;
+
+pub use treeconfig::{
+    Charset, CycleMode, DirsOrder, NameStyle, OutputFormat, PermsFormat, PrintConfig, SizeFormat,
+    SizeKind, SortMode, TimeField, TreeConfig,
+};
+pub use treefile::{
+    walk, DirStatus, Directory, DiskUsage, File, FileTree, Omitted, Summary, Symlink,
+};
+pub use treelibs::{run, Warn};
+pub use treepattern::Pattern;
@@ -4,18 +4,22 @@
     tree [-adfghilnopqrstuvxACDFNS] [-L level [-R]] [-H baseHREF] [-T title] [-o filename] [--nolinks] [-P pattern] [-I pattern] [--inodes] [--device] [--noreport] [--dirsfirst] [--version] [--help] [--filelimit #] [directory ...]
 */
 
-//  //  #[macro_use]
-//  //  extern crate Parser;
-
-//  extern crate tree;
-mod treelibs;
-//  use treelibs::*;
-
 use clap::Parser;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::IsTerminal;
+use std::io::Write;
 use std::path::PathBuf;
 
 use std::process;
 
+use f_111_semi::{
+    Charset, CycleMode, DirsOrder, NameStyle, OutputFormat, Pattern, PermsFormat, PrintConfig,
+    SizeFormat, SizeKind, SortMode, TimeField, TreeConfig,
+};
+
 /// A tree clone written in Rust
 #[derive(Parser, Debug)]
 //  #[Parser(name = "rstree")]
@@ -48,18 +52,90 @@ pub struct Opt {
     #[clap(short = 'i', default_value = "false")]
     no_indent: bool,
 
-    /// Colorize output
-    #[clap(short = 'c', default_value = "false", group = "printy_style")]
+    /// Print the characters of names which could garble the output as "?" : control characters, bidirectional overrides, bytes which are not UTF-8
+    #[clap(short = 'q', default_value = "false", group = "name_style")]
+    question_marks: bool,
+
+    /// Print the bytes of names as they are, even control characters and bytes which are not UTF-8
+    #[clap(short = 'N', default_value = "false", group = "name_style")]
+    raw_names: bool,
+
+    /// Print names between double quotes, with '"' and '\\' escaped too
+    #[clap(short = 'Q', default_value = "false", group = "name_style")]
+    quote_names: bool,
+
+    /// Lines drawn with : utf8, ascii ("|-- "), vt100, or 4 characters of your own for the vertical line, tee, elbow and horizontal line : "|+\\-"
+    #[clap(long, value_name = "CHARSET", value_parser = parse_charset, group = "charset_mode")]
+    charset: Option<Charset>,
+
+    /// Draw lines with the graphics characters of VT100 terminals, same as --charset=vt100
+    #[clap(short = 'A', default_value = "false", group = "charset_mode")]
+    vt100: bool,
+
+    /// Width of each level of indentation, lines included
+    #[clap(long, value_name = "WIDTH", default_value = "4", value_parser = clap::value_parser!(u16).range(2..))]
+    indent: u16,
+
+    /// Colorize output, same as -C
+    #[clap(short = 'c', default_value = "false", group = "color_mode")]
     colorize: bool,
 
+    /// Colorize output, even when it is not a terminal, same as --color=always
+    #[clap(short = 'C', default_value = "false", group = "color_mode")]
+    force_color: bool,
+
+    /// Never colorize output, same as --color=never
+    #[clap(short = 'n', default_value = "false", group = "color_mode")]
+    no_color: bool,
+
+    /// When to colorize output : auto (on a terminal when TREE_COLORS or LS_COLORS is set, unless NO_COLOR is set to a non-empty value, or when CLICOLOR_FORCE forces it), always, never
+    #[clap(long, value_name = "WHEN", value_parser = ["auto", "always", "never"], group = "color_mode")]
+    color: Option<String>,
+
     /// Print file type and permissions, as per "ls -l"
     #[clap(short = 'p', default_value = "false", group = "permissions")]
     perms: bool,
 
-    /// Print numerical file permissions
+    /// Print file permissions in 4-digit octal : 0755, 4755...
     #[clap(long, default_value = "false", group = "permissions")]
     num_perms: bool,
 
+    /// Print the disk usage of each directory : the allocated size of its whole subtree, implies -s
+    #[clap(long, default_value = "false")]
+    du: bool,
+
+    /// With --du, sum the lengths of the files instead of their allocated size
+    #[clap(long, default_value = "false", requires = "du")]
+    apparent_size: bool,
+
+    /// Print the owner of each file, by name
+    #[clap(short = 'u', default_value = "false")]
+    user: bool,
+
+    /// Print the group of each file, by name
+    #[clap(short = 'g', default_value = "false")]
+    group: bool,
+
+    /// Print user and group ids instead of their names, with -u and -g
+    #[clap(long, default_value = "false")]
+    numeric_ids: bool,
+
+    /// Print the date of last modification of each file, or the one chosen by --time
+    #[clap(short = 'D', default_value = "false")]
+    date: bool,
+
+    /// Format of the dates, as strftime : "%Y-%m-%d %H:%M", implies -D
+    #[clap(long, value_name = "format")]
+    timefmt: Option<String>,
+
+    /// Date printed by -D : mtime (last modification), ctime (last status change), atime (last access) or btime (creation), implies -D
+    #[clap(long, value_parser = ["mtime", "ctime", "atime", "btime"])]
+    time: Option<String>,
+
+    /// Print dates as their age : "3h ago", implies -D
+    #[clap(long, default_value = "false")]
+    reltime: bool,
+
     /// Print file size in bytes
     #[clap(short = 's', default_value = "false", group = "filesize")]
     size: bool,
@@ -72,14 +148,62 @@ pub struct Opt {
     #[clap(long, default_value = "false", group = "filesize")]
     hsize: bool,
 
-    /// Ignore cycle avoidance methods, faster but risks symlink cycles
+    /// Only stop symlink cycles, directories reached by several links are listed each time
     #[clap(long, default_value = "false", group = "sym_cycle_mode")]
     fast_rsc: bool,
 
-    /// Advanced cycle detection and avoidance by pre-computing parents of target file
+    /// Also stop links to the parents of the base directory
     #[clap(long, default_value = "false", group = "sym_cycle_mode")]
     ladv: bool,
 
+    /// List only files matching the pattern : *, ?, [...], ** and | alternation are supported
+    #[clap(short = 'P')]
+    include: Vec<String>,
+
+    /// Do not list files matching the pattern
+    #[clap(short = 'I')]
+    exclude: Vec<String>,
+
+    /// Apply -P patterns to directory names too
+    #[clap(long, default_value = "false")]
+    matchdirs: bool,
+
+    /// Ignore case when matching -P and -I patterns
+    #[clap(long, default_value = "false")]
+    ignore_case: bool,
+
+    /// Do not list files ignored by .gitignore, .ignore and git exclude files
+    #[clap(long, default_value = "false")]
+    gitignore: bool,
+
+    /// Sort entries by : name, version, size (largest first), mtime or ctime (oldest first), none
+    #[clap(long, value_parser = ["name", "version", "size", "mtime", "ctime", "none"], group = "sort_mode")]
+    sort: Option<String>,
+
+    /// Sort entries by version, "file2" before "file10"
+    #[clap(short = 'v', default_value = "false", group = "sort_mode")]
+    version_sort: bool,
+
+    /// Sort entries by last modification time
+    #[clap(short = 't', default_value = "false", group = "sort_mode")]
+    mtime_sort: bool,
+
+    /// Leave entries unsorted, in directory order, and print them as soon as they are read
+    #[clap(short = 'U', long, default_value = "false", group = "sort_mode")]
+    unsorted: bool,
+
+    /// Reverse the order of the sort
+    #[clap(short = 'r', default_value = "false")]
+    reverse: bool,
+
+    /// List directories before files
+    #[clap(long, default_value = "false", group = "dirs_order")]
+    dirsfirst: bool,
+
+    /// List directories after files
+    #[clap(long, default_value = "false", group = "dirs_order")]
+    filesfirst: bool,
+
     /// Set the depth of the iteraton, if 0 it goes to depth infinity
     #[clap(short = 'L', default_value = "0")]
     level: usize,
@@ -88,21 +212,279 @@ pub struct Opt {
     #[clap(long, default_value = "0")]
     filelimit: usize,
 
-    /// Directory to start with
+    /// List only the # largest entries of each directory, by their --du total with --du
+    #[clap(long, value_name = "#", default_value = "0")]
+    top: usize,
+
+    /// Do not list the directories left empty by -P, -I, -d or --gitignore
+    #[clap(long, default_value = "false")]
+    prune: bool,
+
+    /// Stay on the filesystem of the base directory, mount points are not descended
+    #[clap(short = 'x', long, default_value = "false")]
+    one_file_system: bool,
+
+    /// Read directories with # threads, the output is the same as with one ; -U, printing as it reads, uses a single thread
+    #[clap(long, default_value = "1")]
+    threads: usize,
+
+    /// Directories to start with, listed one after the other with a single report
     #[clap(name = "DIRECTORY", default_value = ".")]
-    directory: PathBuf,
+    directories: Vec<PathBuf>,
+
+    /// Omit the final report of directories and files
+    #[clap(long, default_value = "false")]
+    noreport: bool,
+
+    /// Print the tree as JSON
+    #[clap(short = 'J', default_value = "false", group = "output_format")]
+    json: bool,
+
+    /// Print the tree as XML
+    #[clap(short = 'X', default_value = "false", group = "output_format")]
+    xml: bool,
+
+    /// Print the tree as an HTML page, with hyperlinks rooted in baseHREF
+    #[clap(short = 'H', value_name = "baseHREF", group = "output_format")]
+    html_base: Option<String>,
+
+    /// Title of the HTML page
+    #[clap(short = 'T', default_value = "Directory Tree")]
+    title: String,
+
+    /// Print directories of the HTML page as collapsible nodes
+    #[clap(long, default_value = "false")]
+    collapsible: bool,
 
     /// Save to target file
-    #[clap(short = 'o', default_value = "", group = "printy_style")]
+    #[clap(short = 'o', default_value = "")]
     target_file: String,
 }
 
+impl Opt {
+    fn size_kind(&self) -> SizeKind {
+        match (self.du, self.apparent_size) {
+            (false, _) => SizeKind::Length,
+            (true, false) => SizeKind::Allocated,
+            (true, true) => SizeKind::Apparent,
+        }
+    }
+
+    fn tree_config(&self) -> TreeConfig {
+        let cycle_mode = if self.fast_rsc {
+            CycleMode::Fast
+        } else if self.ladv {
+            CycleMode::Advanced
+        } else {
+            CycleMode::Standard
+        };
+        let sort = if self.version_sort {
+            SortMode::Version
+        } else if self.mtime_sort {
+            SortMode::Mtime
+        } else if self.unsorted {
+            SortMode::None
+        } else {
+            match self.sort.as_deref() {
+                Some("version") => SortMode::Version,
+                Some("size") => SortMode::Size,
+                Some("mtime") => SortMode::Mtime,
+                Some("ctime") => SortMode::Ctime,
+                Some("none") => SortMode::None,
+                _ => SortMode::Name,
+            }
+        };
+        let dirs_order = if self.dirsfirst {
+            DirsOrder::DirsFirst
+        } else if self.filesfirst {
+            DirsOrder::FilesFirst
+        } else {
+            DirsOrder::Mixed
+        };
+        let mut cfg = TreeConfig::new()
+            .show_hidden(self.show_hidden)
+            .only_dir(self.only_dir)
+            .follow_symlink(self.follow_symlink)
+            .level(self.level)
+            .filelimit(self.filelimit)
+            .threads(self.threads)
+            .one_file_system(self.one_file_system)
+            .du(self.size_kind())
+            .top(self.top)
+            .prune(self.prune)
+            .cycle_mode(cycle_mode)
+            .match_dirs(self.matchdirs)
+            .gitignore(self.gitignore)
+            .sort(sort)
+            .reverse(self.reverse)
+            .dirs_order(dirs_order);
+        for pattern in &self.include {
+            cfg = cfg.include(Pattern::new(pattern, self.ignore_case));
+        }
+        for pattern in &self.exclude {
+            cfg = cfg.exclude(Pattern::new(pattern, self.ignore_case));
+        }
+        cfg
+    }
+
+    // with auto, NO_COLOR wins over CLICOLOR_FORCE, which wins over the terminal check
+    // as GNU tree does, auto leaves the output uncolored unless TREE_COLORS or LS_COLORS is set
+    fn colorize_output(&self) -> bool {
+        let when = if self.colorize || self.force_color {
+            "always"
+        } else if self.no_color {
+            "never"
+        } else {
+            self.color.as_deref().unwrap_or("auto")
+        };
+        // NO_COLOR set to a non-empty value, CLICOLOR_FORCE to one other than "0"
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let force =
+            env::var_os("CLICOLOR_FORCE").is_some_and(|value| !value.is_empty() && value != "0");
+        let palette = ["TREE_COLORS", "LS_COLORS"]
+            .iter()
+            .any(|var| env::var_os(var).is_some_and(|value| !value.is_empty()));
+        match when {
+            "always" => true,
+            "never" => false,
+            _ if no_color => false,
+            _ if force => true,
+            _ => palette && self.target_file.is_empty() && io::stdout().is_terminal(),
+        }
+    }
+
+    fn print_config(&self) -> PrintConfig {
+        let perms = if self.num_perms {
+            PermsFormat::Numeric
+        } else if self.perms {
+            PermsFormat::Symbolic
+        } else {
+            PermsFormat::None
+        };
+        let size = if self.size {
+            SizeFormat::Bytes
+        } else if self.hsize_ib {
+            SizeFormat::HumanSi
+        } else if self.hsize {
+            SizeFormat::HumanIec
+        } else if self.du {
+            SizeFormat::Bytes
+        } else {
+            SizeFormat::None
+        };
+        let names = if self.question_marks {
+            NameStyle::Question
+        } else if self.raw_names {
+            NameStyle::Raw
+        } else if self.quote_names {
+            NameStyle::Quoted
+        } else {
+            NameStyle::Escaped
+        };
+        let time_field = match self.time.as_deref() {
+            Some("ctime") => TimeField::Ctime,
+            Some("atime") => TimeField::Atime,
+            Some("btime") => TimeField::Btime,
+            _ => TimeField::Mtime,
+        };
+        let format = if self.json {
+            OutputFormat::Json
+        } else if self.xml {
+            OutputFormat::Xml
+        } else if self.html_base.is_some() {
+            OutputFormat::Html
+        } else {
+            OutputFormat::Text
+        };
+        PrintConfig::new()
+            .keep_canonical(self.keep_canonical)
+            .full_rel_path(self.full_rel_path)
+            .base_canonical(self.base_canonical)
+            .no_indent(self.no_indent)
+            .charset(match self.vt100 {
+                true => Charset::Vt100,
+                false => self.charset.unwrap_or_default(),
+            })
+            .indent(usize::from(self.indent))
+            .colorize(self.colorize_output())
+            .names(names)
+            .perms(perms)
+            .size_kind(self.size_kind())
+            .user(self.user)
+            .group(self.group)
+            .numeric_ids(self.numeric_ids)
+            .date(self.date || self.timefmt.is_some() || self.time.is_some() || self.reltime)
+            .time_field(time_field)
+            .timefmt(self.timefmt.as_deref().unwrap_or_default())
+            .relative_time(self.reltime)
+            .size(size)
+            .noreport(self.noreport)
+            .format(format)
+            .html_base(self.html_base.as_deref().unwrap_or_default())
+            .html_title(&self.title)
+            .html_collapsible(self.collapsible)
+    }
+}
+
+// --charset : a name, or the 4 characters of a custom charset
+fn parse_charset(value: &str) -> Result<Charset, String> {
+    match value {
+        "utf8" | "utf-8" => Ok(Charset::Utf8),
+        "ascii" => Ok(Charset::Ascii),
+        "vt100" => Ok(Charset::Vt100),
+        _ => match value.chars().collect::<Vec<_>>()[..] {
+            [vertical, tee, elbow, horizontal] => {
+                Ok(Charset::Custom([vertical, tee, elbow, horizontal]))
+            }
+            _ => Err("expected utf8, ascii, vt100, or 4 characters".to_string()),
+        },
+    }
+}
+
+//  exit codes : everything listed, some entries could not be read, nothing listed
+const EXIT_OK: i32 = 0;
+const EXIT_PARTIAL: i32 = 1;
+const EXIT_FATAL: i32 = 2;
+
 fn main() {
     let opt = Opt::parse();
     //  println!("{:?}", opt);
 
-    if let Err(_run_error) = treelibs::run(&opt) {
-        //  eprintln!("Application error: {}", _run_error);
-        process::exit(1);
+    //  solution without heap allocations :
+    let outfile: &mut dyn Write;
+    let mut lockstdout;
+    let mut buf_file;
+
+    if opt.target_file.is_empty() {
+        let stdout = std::io::stdout();
+        lockstdout = stdout.lock();
+        outfile = &mut lockstdout;
+    } else {
+        match File::create(&opt.target_file) {
+            Ok(file) => {
+                buf_file = BufWriter::new(file);
+                outfile = &mut buf_file;
+            }
+            Err(create_error) => {
+                eprintln!("{}: {}", opt.target_file, create_error);
+                process::exit(EXIT_FATAL);
+            }
+        }
+    }
+
+    match f_111_semi::run(
+        outfile,
+        &opt.directories,
+        &opt.tree_config(),
+        &opt.print_config(),
+        &mut |path, err| eprintln!("warning: {}: {}", path.display(), err),
+    ) {
+        Ok(summary) if summary.errors > 0 => process::exit(EXIT_PARTIAL),
+        Ok(_) => process::exit(EXIT_OK),
+        Err(run_error) => {
+            // errors of a base already name it
+            eprintln!("{}", run_error);
+            process::exit(EXIT_FATAL);
+        }
     }
 }
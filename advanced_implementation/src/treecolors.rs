@@ -0,0 +1,291 @@
+//  colors of the entries, for -c
+//  read once from TREE_COLORS, or else LS_COLORS, in the format of dircolors :
+//  "di=01;34:ln=01;36:*.tar=01;31:...". the codes are SGR parameters, written as they are :
+//  256 colors (38;5;208) and truecolor (38;2;255;128;0) work as well as the 8 basic ones.
+//  entries are classified as ls does : type keys first, then the "*suffix" rules for plain files.
+//  without either variable, directories are yellow, executables green, other files magenta,
+//  symlinks cyan and orphan symlinks red.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fs::Metadata;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::treefile::FileTree;
+
+const DEFAULT_COLORS: &str =
+    "di=0;33:ex=0;32:fi=0;35:pi=0;35:so=0;35:bd=0;35:cd=0;35:ln=0;36:or=0;31";
+
+static COLORS: OnceLock<Colors> = OnceLock::new();
+
+struct Colors {
+    /// codes of the type keys : "di", "ln", "ex"...
+    types: HashMap<String, String>,
+    /// codes of the "*suffix" rules, in the order they are defined
+    suffixes: Vec<(String, String)>,
+    /// "ln=target" : symlinks take the color of their target
+    link_as_target: bool,
+}
+
+// "name" of "node" in its color, unchanged if it has none
+// suffix rules match the file name itself, "name" may be quoted or escaped, its bytes are kept
+pub(crate) fn paint_name(node: &FileTree, name: &[u8]) -> Vec<u8> {
+    let colors = colors();
+    let file_name = file_name(node.path());
+    let codes = match node {
+        FileTree::LinkNode(link) => match &link.target_metadata {
+            None => colors.get("or").or_else(|| colors.get("ln")),
+            Some(target_mtd) if colors.link_as_target => colors.file_codes(target_mtd, &file_name),
+            Some(_) => colors.get("ln"),
+        },
+        _ => node
+            .metadata()
+            .and_then(|mtd| colors.file_codes(mtd, &file_name)),
+    };
+    colors.paint(codes, name)
+}
+
+// "target" of the symlink "node" in the color of what it points to, "mi" if it is missing
+pub(crate) fn paint_target(node: &FileTree, target: &[u8]) -> Vec<u8> {
+    let colors = colors();
+    let codes = match node {
+        FileTree::LinkNode(link) => match (&link.target_metadata, &link.target) {
+            (Some(target_mtd), Some(target_path)) => {
+                colors.file_codes(target_mtd, &file_name(target_path))
+            }
+            (Some(_), None) => None,
+            (None, _) => colors.get("mi"),
+        },
+        _ => None,
+    };
+    colors.paint(codes, target)
+}
+
+fn file_name(path: &Path) -> Cow<'_, str> {
+    path.file_name().unwrap_or_default().to_string_lossy()
+}
+
+fn colors() -> &'static Colors {
+    COLORS.get_or_init(|| {
+        let spec = ["TREE_COLORS", "LS_COLORS"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|spec| !spec.is_empty());
+        Colors::parse(spec.as_deref().unwrap_or(DEFAULT_COLORS))
+    })
+}
+
+impl Colors {
+    fn parse(spec: &str) -> Colors {
+        let mut colors = Colors {
+            types: HashMap::new(),
+            suffixes: Vec::new(),
+            link_as_target: false,
+        };
+        for (key, value) in spec.split(':').filter_map(|item| item.split_once('=')) {
+            let value = unescape(value);
+            if let Some(suffix) = key.strip_prefix('*') {
+                colors.suffixes.push((suffix.to_string(), value));
+            } else if key == "ln" && value == "target" {
+                colors.link_as_target = true;
+            } else {
+                colors.types.insert(key.to_string(), value);
+            }
+        }
+        colors
+    }
+
+    // codes of a type key, if it colors anything
+    fn get(&self, key: &str) -> Option<&str> {
+        self.types
+            .get(key)
+            .map(String::as_str)
+            .filter(|codes| !matches!(*codes, "" | "0" | "00"))
+    }
+
+    // codes of a file whose metadata, symlinks followed or not, is "mtd"
+    fn file_codes(&self, mtd: &Metadata, name: &str) -> Option<&str> {
+        let file_type = mtd.file_type();
+        let mode = mtd.permissions().mode();
+        if file_type.is_dir() {
+            // sticky and other-writable, other-writable, sticky
+            return [(0o1002, "tw"), (0o0002, "ow"), (0o1000, "st")]
+                .iter()
+                .filter(|(bits, _)| mode & bits == *bits)
+                .find_map(|(_, key)| self.get(key))
+                .or_else(|| self.get("di"));
+        }
+        if file_type.is_symlink() {
+            return self.get("ln");
+        }
+        if file_type.is_fifo() {
+            return self.get("pi");
+        }
+        if file_type.is_socket() {
+            return self.get("so");
+        }
+        if file_type.is_block_device() {
+            return self.get("bd");
+        }
+        if file_type.is_char_device() {
+            return self.get("cd");
+        }
+        // setuid, setgid, executable
+        [(0o4000, "su"), (0o2000, "sg"), (0o0111, "ex")]
+            .iter()
+            .filter(|(bits, _)| mode & bits != 0)
+            .find_map(|(_, key)| self.get(key))
+            .or_else(|| self.suffix_codes(name))
+            .or_else(|| self.get("fi"))
+    }
+
+    // the last rule matching the end of "name", exactly or else ignoring case
+    fn suffix_codes(&self, name: &str) -> Option<&str> {
+        let exact = self
+            .suffixes
+            .iter()
+            .rev()
+            .find(|(suffix, _)| name.ends_with(suffix.as_str()));
+        let rule = exact.or_else(|| {
+            let name = name.to_lowercase();
+            self.suffixes
+                .iter()
+                .rev()
+                .find(|(suffix, _)| name.ends_with(&suffix.to_lowercase()))
+        });
+        rule.map(|(_, codes)| codes.as_str())
+    }
+
+    // "text" between the escape sequences of "codes"
+    fn paint(&self, codes: Option<&str>, text: &[u8]) -> Vec<u8> {
+        let codes = match codes {
+            Some(codes) => codes,
+            None => return text.to_vec(),
+        };
+        let left = self.types.get("lc").map_or("\u{1B}[", String::as_str);
+        let right = self.types.get("rc").map_or("m", String::as_str);
+        let end = match self.types.get("ec") {
+            Some(end) => end.clone(),
+            None => {
+                let reset = self.types.get("rs").map_or("0", String::as_str);
+                format!("{}{}{}", left, reset, right)
+            }
+        };
+        let mut painted = format!("{}{}{}", left, codes, right).into_bytes();
+        painted.extend_from_slice(text);
+        painted.extend_from_slice(end.as_bytes());
+        painted
+    }
+}
+
+// escapes of dircolors values : "\e", "\033", "^[" ...
+fn unescape(value: &str) -> String {
+    let mut text = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('e') => text.push('\u{1B}'),
+                Some('a') => text.push('\u{7}'),
+                Some('b') => text.push('\u{8}'),
+                Some('f') => text.push('\u{C}'),
+                Some('n') => text.push('\n'),
+                Some('r') => text.push('\r'),
+                Some('t') => text.push('\t'),
+                Some('v') => text.push('\u{B}'),
+                Some('_') => text.push(' '),
+                Some(digit @ '0'..='7') => {
+                    let mut code = digit.to_digit(8).unwrap_or(0);
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|c| c.to_digit(8)) {
+                            Some(next) => {
+                                code = code * 8 + next;
+                                chars.next();
+                            }
+                            None => break,
+                        }
+                    }
+                    text.extend(char::from_u32(code));
+                }
+                Some(other) => text.push(other),
+                None => {}
+            },
+            '^' => match chars.next() {
+                Some('?') => text.push('\u{7F}'),
+                Some(other) => text.extend(char::from_u32(other as u32 & 0x1F)),
+                None => text.push('^'),
+            },
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unescape;
+    use super::Colors;
+
+    #[test]
+    fn parse() {
+        let colors = Colors::parse("di=01;34:ln=target:ex=00:*.tar=01;31:*.TXT=0;33:bad:=1");
+        assert!(colors.link_as_target);
+        assert_eq!(colors.get("di"), Some("01;34"));
+        assert_eq!(colors.get("ln"), None);
+        assert_eq!(colors.get("ex"), None);
+        assert_eq!(colors.suffixes.len(), 2);
+        assert!(!Colors::parse("ln=01;36").link_as_target);
+    }
+
+    #[test]
+    fn suffixes() {
+        let colors = Colors::parse("*.tar=01;31:*.gz=01;32:*.tar.gz=01;35:*.TXT=0;33:*.txt=0;34");
+        assert_eq!(colors.suffix_codes("a.tar"), Some("01;31"));
+        // the last rule matching wins
+        assert_eq!(colors.suffix_codes("a.tar.gz"), Some("01;35"));
+        // case is ignored only without an exact match
+        assert_eq!(colors.suffix_codes("a.txt"), Some("0;34"));
+        assert_eq!(colors.suffix_codes("a.TXT"), Some("0;33"));
+        assert_eq!(colors.suffix_codes("a.Tar"), Some("01;31"));
+        assert_eq!(colors.suffix_codes("tar"), None);
+    }
+
+    #[test]
+    fn extended_colors() {
+        let colors = Colors::parse("di=38;5;208:ex=1;38;2;255;128;0");
+        assert_eq!(
+            colors.paint(colors.get("di"), b"dir"),
+            b"\x1b[38;5;208mdir\x1b[0m"
+        );
+        assert_eq!(
+            colors.paint(colors.get("ex"), b"run"),
+            b"\x1b[1;38;2;255;128;0mrun\x1b[0m"
+        );
+        assert_eq!(colors.paint(None, b"plain"), b"plain");
+    }
+
+    #[test]
+    fn sequences() {
+        let colors = Colors::parse("lc=\\e[:rc=m:ec=\\e[m:di=34");
+        assert_eq!(colors.paint(colors.get("di"), b"d"), b"\x1b[34md\x1b[m");
+        let colors = Colors::parse("rs=00:di=34");
+        assert_eq!(colors.paint(colors.get("di"), b"d"), b"\x1b[34md\x1b[00m");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(unescape("\\e[1m"), "\x1b[1m");
+        assert_eq!(unescape("\\033[1m"), "\x1b[1m");
+        assert_eq!(unescape("\\33"), "\x1b");
+        assert_eq!(unescape("^[[1m"), "\x1b[1m");
+        assert_eq!(unescape("^?"), "\x7f");
+        assert_eq!(unescape("a\\_b"), "a b");
+        assert_eq!(unescape("\\\\\\^"), "\\^");
+        assert_eq!(unescape("01;34"), "01;34");
+        assert_eq!(unescape("trail^"), "trail^");
+    }
+}
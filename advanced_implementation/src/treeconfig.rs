@@ -0,0 +1,462 @@
+//  configuration of the tree walker and of the printer,
+//  built with chained setters so that callers do not need to fake CLI arguments :
+//      let cfg = TreeConfig::new().show_hidden(true).level(3);
+
+use crate::treepattern::Pattern;
+
+/// How symlink cycles are avoided while following symlinks (-l)
+/// a link to one of its own ancestor directories, by (st_dev, st_ino), is never followed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CycleMode {
+    /// Only stop true cycles, a directory reached by several links is listed each time (--fast_rsc)
+    Fast,
+    /// Also list each directory once, links to a directory already listed are not followed
+    #[default]
+    Standard,
+    /// As Standard, the parents of the base directory also count as ancestors (--ladv)
+    Advanced,
+}
+
+/// Key used to sort the entries of each directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortMode {
+    #[default]
+    Name,
+    /// Natural sort of the names : "file2" before "file10" (-v)
+    Version,
+    /// Largest first
+    Size,
+    /// Oldest modification first (-t)
+    Mtime,
+    /// Oldest status change first
+    Ctime,
+    /// Directory order, as read : entries are printed as soon as they are read,
+    /// with bounded memory, unless DirsOrder::Mixed is changed (-U)
+    None,
+}
+
+/// Grouping of directories with respect to the other entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DirsOrder {
+    #[default]
+    Mixed,
+    /// Directories before the other entries (--dirsfirst)
+    DirsFirst,
+    /// Directories after the other entries (--filesfirst)
+    FilesFirst,
+}
+
+/// Which entries the walker reads, and how deep
+#[derive(Debug, Clone, Default)]
+pub struct TreeConfig {
+    pub(crate) show_hidden: bool,
+    pub(crate) only_dir: bool,
+    pub(crate) follow_symlink: bool,
+    pub(crate) level: usize,
+    pub(crate) filelimit: usize,
+    pub(crate) cycle_mode: CycleMode,
+    pub(crate) include: Vec<Pattern>,
+    pub(crate) exclude: Vec<Pattern>,
+    pub(crate) match_dirs: bool,
+    pub(crate) gitignore: bool,
+    pub(crate) sort: SortMode,
+    pub(crate) reverse: bool,
+    pub(crate) dirs_order: DirsOrder,
+    pub(crate) threads: usize,
+    pub(crate) one_file_system: bool,
+    pub(crate) du: SizeKind,
+    pub(crate) top: usize,
+    pub(crate) prune: bool,
+}
+
+impl TreeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read all files, including hidden
+    pub fn show_hidden(mut self, show_hidden: bool) -> Self {
+        self.show_hidden = show_hidden;
+        self
+    }
+
+    /// Keep only directories
+    pub fn only_dir(mut self, only_dir: bool) -> Self {
+        self.only_dir = only_dir;
+        self
+    }
+
+    /// Follow sym-links if they point to directories, as if they were directories
+    pub fn follow_symlink(mut self, follow_symlink: bool) -> Self {
+        self.follow_symlink = follow_symlink;
+        self
+    }
+
+    /// Max depth of the walk, 0 goes to depth infinity
+    pub fn level(mut self, level: usize) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Do not descend directories with more than # entries, 0 means no bound
+    pub fn filelimit(mut self, filelimit: usize) -> Self {
+        self.filelimit = filelimit;
+        self
+    }
+
+    pub fn cycle_mode(mut self, cycle_mode: CycleMode) -> Self {
+        self.cycle_mode = cycle_mode;
+        self
+    }
+
+    /// Keep only files matching one of the include patterns (-P), can be repeated
+    pub fn include(mut self, pattern: Pattern) -> Self {
+        self.include.push(pattern);
+        self
+    }
+
+    /// Drop files and directories matching one of the exclude patterns (-I), can be repeated
+    pub fn exclude(mut self, pattern: Pattern) -> Self {
+        self.exclude.push(pattern);
+        self
+    }
+
+    /// Apply include patterns to directory names too :
+    /// the contents of a matching directory are kept as a whole
+    pub fn match_dirs(mut self, match_dirs: bool) -> Self {
+        self.match_dirs = match_dirs;
+        self
+    }
+
+    /// Skip entries ignored by .gitignore, .ignore, .git/info/exclude and the global excludes file
+    pub fn gitignore(mut self, gitignore: bool) -> Self {
+        self.gitignore = gitignore;
+        self
+    }
+
+    pub fn sort(mut self, sort: SortMode) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Reverse the order of the sort, has no effect with SortMode::None (-r)
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    pub fn dirs_order(mut self, dirs_order: DirsOrder) -> Self {
+        self.dirs_order = dirs_order;
+        self
+    }
+
+    /// Do not descend into directories on another filesystem than the base (-x)
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Compute the size of the whole subtree of each directory, unlisted entries included (--du)
+    /// Apparent or Allocated is also the size used by SortMode::Size and top(), Length computes none
+    pub fn du(mut self, du: SizeKind) -> Self {
+        self.du = du;
+        self
+    }
+
+    /// Keep only the # largest entries of each directory, 0 keeps them all (--top)
+    /// the others are counted in Directory::omitted
+    pub fn top(mut self, top: usize) -> Self {
+        self.top = top;
+        self
+    }
+
+    /// Leave out the directories left empty by the filters, once their subtree is walked (--prune)
+    /// directories not read, beyond the max depth or --filelimit, are kept
+    pub fn prune(mut self, prune: bool) -> Self {
+        self.prune = prune;
+        self
+    }
+
+    // unsorted trees are printed while they are walked, without a model of the whole tree
+    pub(crate) fn streams(&self) -> bool {
+        self.sort == SortMode::None
+            && self.dirs_order == DirsOrder::Mixed
+            && self.du == SizeKind::Length
+            && self.top == 0
+            && !self.prune
+    }
+
+    // the walk keeps every entry it reads : nothing hidden, filtered or pruned
+    pub(crate) fn lists_everything(&self) -> bool {
+        self.show_hidden
+            && self.include.is_empty()
+            && self.exclude.is_empty()
+            && !self.gitignore
+            && !self.only_dir
+            && !self.prune
+    }
+
+    /// Read directories with # threads ahead of the walk, 0 or 1 reads them one at a time
+    /// the tree is the same whatever the number of threads
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+}
+
+/// Format of the permissions in the info column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PermsFormat {
+    #[default]
+    None,
+    /// File type and permissions, as per "ls -l" (-p)
+    Symbolic,
+    /// Permissions in 4-digit octal, special bits included : 0755, 4755... (--num_perms)
+    Numeric,
+}
+
+/// Format of the sizes in the info column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeFormat {
+    #[default]
+    None,
+    /// Size in bytes (-s)
+    Bytes,
+    /// Human readable, powers of 10^3 : K, M, G... (--hsize_ib)
+    HumanSi,
+    /// Human readable, powers of 2^10 : Ki, Mi, Gi... (--hsize)
+    HumanIec,
+}
+
+/// Which size of the entries is printed, and compared by --sort=size and --top
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeKind {
+    /// st_size, for directories the size of the directory itself
+    #[default]
+    Length,
+    /// st_size, for directories the sum over their subtree, with TreeConfig::du (--apparent-size)
+    Apparent,
+    /// Allocated blocks, for directories the sum over their subtree, with TreeConfig::du (--du)
+    Allocated,
+}
+
+/// Which timestamp of the files is printed by -D
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeField {
+    /// Last modification
+    #[default]
+    Mtime,
+    /// Last status change
+    Ctime,
+    /// Last access
+    Atime,
+    /// Creation, not recorded by every filesystem
+    Btime,
+}
+
+/// How the characters of file names which could garble the output are printed :
+/// control characters, bidirectional overrides and bytes which are not UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameStyle {
+    /// As \x.. escapes of their bytes
+    #[default]
+    Escaped,
+    /// As "?" (-q)
+    Question,
+    /// As they are, byte for byte (-N) ; JSON, XML and HTML print bytes which are not UTF-8 as U+FFFD
+    Raw,
+    /// As escapes, '"' and '\' escaped too, the name between double quotes (-Q)
+    Quoted,
+}
+
+/// Characters drawing the lines of the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Charset {
+    /// Box drawing characters : "├── ", "└── "
+    #[default]
+    Utf8,
+    /// "|-- ", "`-- "
+    Ascii,
+    /// Line drawing characters of VT100 terminals, in their graphics mode (-A)
+    Vt100,
+    /// User-defined vertical line, tee, elbow and horizontal line : ['|', '+', '\\', '-']
+    Custom([char; 4]),
+}
+
+/// Layout of the whole output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Indented lines, as tree does
+    #[default]
+    Text,
+    /// Nested JSON objects, as GNU tree -J does
+    Json,
+    /// Nested XML elements, as GNU tree -X does
+    Xml,
+    /// HTML page with hyperlinks, as GNU tree -H does
+    Html,
+}
+
+/// How each entry of the tree is printed
+#[derive(Debug, Clone, Default)]
+pub struct PrintConfig {
+    pub(crate) keep_canonical: bool,
+    pub(crate) full_rel_path: bool,
+    pub(crate) base_canonical: bool,
+    pub(crate) no_indent: bool,
+    pub(crate) charset: Charset,
+    pub(crate) indent: usize,
+    pub(crate) colorize: bool,
+    pub(crate) names: NameStyle,
+    pub(crate) perms: PermsFormat,
+    pub(crate) size: SizeFormat,
+    pub(crate) size_kind: SizeKind,
+    pub(crate) user: bool,
+    pub(crate) group: bool,
+    pub(crate) numeric_ids: bool,
+    pub(crate) date: bool,
+    pub(crate) time_field: TimeField,
+    pub(crate) timefmt: String,
+    pub(crate) relative_time: bool,
+    pub(crate) format: OutputFormat,
+    pub(crate) html_base: String,
+    pub(crate) html_title: String,
+    pub(crate) html_collapsible: bool,
+    pub(crate) noreport: bool,
+}
+
+impl PrintConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Print full canonicalized path
+    pub fn keep_canonical(mut self, keep_canonical: bool) -> Self {
+        self.keep_canonical = keep_canonical;
+        self
+    }
+
+    /// Print complete relative path prefix for all
+    pub fn full_rel_path(mut self, full_rel_path: bool) -> Self {
+        self.full_rel_path = full_rel_path;
+        self
+    }
+
+    /// Print full canonical path of the base directory
+    pub fn base_canonical(mut self, base_canonical: bool) -> Self {
+        self.base_canonical = base_canonical;
+        self
+    }
+
+    /// Don't indent
+    pub fn no_indent(mut self, no_indent: bool) -> Self {
+        self.no_indent = no_indent;
+        self
+    }
+
+    pub fn charset(mut self, charset: Charset) -> Self {
+        self.charset = charset;
+        self
+    }
+
+    /// Width of each level of indentation, lines included, 0 for the default of 4
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    pub fn colorize(mut self, colorize: bool) -> Self {
+        self.colorize = colorize;
+        self
+    }
+
+    pub fn names(mut self, names: NameStyle) -> Self {
+        self.names = names;
+        self
+    }
+
+    pub fn perms(mut self, perms: PermsFormat) -> Self {
+        self.perms = perms;
+        self
+    }
+
+    pub fn size(mut self, size: SizeFormat) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn size_kind(mut self, size_kind: SizeKind) -> Self {
+        self.size_kind = size_kind;
+        self
+    }
+
+    /// Print the owner of each file (-u)
+    pub fn user(mut self, user: bool) -> Self {
+        self.user = user;
+        self
+    }
+
+    /// Print the group of each file (-g)
+    pub fn group(mut self, group: bool) -> Self {
+        self.group = group;
+        self
+    }
+
+    /// Print user and group ids instead of their names (--numeric-ids)
+    pub fn numeric_ids(mut self, numeric_ids: bool) -> Self {
+        self.numeric_ids = numeric_ids;
+        self
+    }
+
+    /// Print the date of each file (-D)
+    pub fn date(mut self, date: bool) -> Self {
+        self.date = date;
+        self
+    }
+
+    pub fn time_field(mut self, time_field: TimeField) -> Self {
+        self.time_field = time_field;
+        self
+    }
+
+    /// strftime format of the dates, "%b %e %H:%M" if empty (--timefmt)
+    pub fn timefmt(mut self, timefmt: &str) -> Self {
+        self.timefmt = timefmt.to_string();
+        self
+    }
+
+    /// Print dates as their age : "3h ago" (--reltime)
+    pub fn relative_time(mut self, relative_time: bool) -> Self {
+        self.relative_time = relative_time;
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Base URL of the hyperlinks, with OutputFormat::Html
+    pub fn html_base(mut self, html_base: &str) -> Self {
+        self.html_base = html_base.to_string();
+        self
+    }
+
+    /// Title of the page, with OutputFormat::Html
+    pub fn html_title(mut self, html_title: &str) -> Self {
+        self.html_title = html_title.to_string();
+        self
+    }
+
+    /// Omit the final report of directories and files (--noreport)
+    pub fn noreport(mut self, noreport: bool) -> Self {
+        self.noreport = noreport;
+        self
+    }
+
+    /// Print directories as collapsible <details> nodes, with OutputFormat::Html
+    pub fn html_collapsible(mut self, html_collapsible: bool) -> Self {
+        self.html_collapsible = html_collapsible;
+        self
+    }
+}
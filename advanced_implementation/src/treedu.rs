@@ -0,0 +1,258 @@
+//  disk usage of the directories, for --du
+//  every directory of the tree gets the total size of its subtree as found on disk :
+//  entries not listed (beyond -L, over --filelimit, filtered or hidden) are counted too,
+//  symlinks are not followed, files with several hard links are counted once.
+//  with -x, other filesystems are not counted.
+//  the entries the walk has read are reused : a directory whose entries were all kept is not
+//  read again, and of the others only the entries not listed are. the subtrees not listed at
+//  all are read with --threads threads, their hard links counted once when they are put
+//  together, in the order of the listing : the totals are the same as with one thread.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::treeconfig::TreeConfig;
+use crate::treefile::DirStatus;
+use crate::treefile::Directory;
+use crate::treefile::DiskUsage;
+use crate::treefile::FileTree;
+
+struct Counter {
+    root_dev: Option<u64>,
+    /// directories read by the walk hold all their entries : nothing hidden or filtered
+    complete: bool,
+    threads: usize,
+    /// hard linked files already counted, as (st_dev, st_ino)
+    seen: HashSet<(u64, u64)>,
+}
+
+/// usage of a subtree which is not listed, counted on its own
+#[derive(Default)]
+struct Subtree {
+    usage: DiskUsage,
+    /// its hard linked files, counted once within it
+    linked: HashMap<(u64, u64), DiskUsage>,
+}
+
+/// an entry of a directory, as found on disk
+enum Item<'a> {
+    /// a file, a symlink or anything but a directory, its metadata
+    Own(Metadata),
+    /// a listed sub-directory, and its metadata
+    Listed(&'a mut Directory, Metadata),
+    /// a sub-directory which is not listed, its subtree read apart
+    Unlisted,
+}
+
+// fill the disk usage of every directory of "tree", "root_dev" with -x
+pub(crate) fn fill_disk_usage(tree: &mut FileTree, root_dev: Option<u64>, cfg: &TreeConfig) {
+    let mut counter = Counter {
+        root_dev,
+        complete: cfg.lists_everything(),
+        threads: cfg.threads,
+        seen: HashSet::new(),
+    };
+    if let Some(directory) = tree.contents_mut() {
+        if let Ok(mtd) = fs::metadata(&directory.path) {
+            counter.fill(directory, &mtd);
+        }
+    }
+}
+
+impl Counter {
+    // "mtd" : metadata of the directory itself, symlinks followed
+    fn fill(&mut self, directory: &mut Directory, mtd: &Metadata) {
+        let mut usage = self.own_usage(mtd);
+        if !self.other_fs(mtd) {
+            let mut subtrees = Vec::new();
+            let items = self.items(directory, &mut subtrees);
+            let mut subtrees = self.read_subtrees(subtrees).into_iter();
+            for item in items {
+                match item {
+                    Item::Own(mtd) => usage.add(self.own_usage(&mtd)),
+                    Item::Listed(sub_dir, mtd) => {
+                        self.fill(sub_dir, &mtd);
+                        usage.add(sub_dir.du.unwrap_or_default());
+                    }
+                    Item::Unlisted => {
+                        if let Some(subtree) = subtrees.next() {
+                            usage.add(self.merge(subtree));
+                        }
+                    }
+                }
+            }
+        }
+        directory.du = Some(usage);
+        // targets of the links followed with -l, counted for themselves only
+        for entry in directory.entries.iter_mut() {
+            if let FileTree::LinkNode(link) = entry {
+                if let (Some(target_dir), Some(mtd)) =
+                    (link.expanded.as_deref_mut(), &link.target_metadata)
+                {
+                    self.fill(target_dir, mtd);
+                }
+            }
+        }
+    }
+
+    // the entries of "directory" on disk : those listed with the metadata already read,
+    // the others read again, the sub-directories not listed pushed to "subtrees"
+    fn items<'d>(
+        &self,
+        directory: &'d mut Directory,
+        subtrees: &mut Vec<(PathBuf, Metadata)>,
+    ) -> Vec<Item<'d>> {
+        let read_all = self.complete && matches!(directory.status, DirStatus::Read);
+        let path = directory.path.clone();
+        let mut items = Vec::new();
+        // listed entries, by name
+        let mut listed: HashMap<OsString, &'d mut FileTree> = HashMap::new();
+        for entry in directory.entries.iter_mut() {
+            if read_all {
+                items.extend(listed_item(entry));
+            } else if let Some(name) = entry.path().file_name() {
+                listed.insert(name.to_os_string(), entry);
+            }
+        }
+        if read_all {
+            return items;
+        }
+        if let Ok(entry_set) = fs::read_dir(&path) {
+            for dir_entry in entry_set.flatten() {
+                if let Some(entry) = listed.remove(&dir_entry.file_name()) {
+                    items.extend(listed_item(entry));
+                    continue;
+                }
+                let mtd = match dir_entry.metadata() {
+                    Ok(mtd) => mtd,
+                    Err(_) => continue,
+                };
+                if mtd.is_dir() {
+                    items.push(Item::Unlisted);
+                    subtrees.push((dir_entry.path(), mtd));
+                } else {
+                    items.push(Item::Own(mtd));
+                }
+            }
+        }
+        items
+    }
+
+    // usage of the "subtrees", in their order, read with the threads of --threads
+    fn read_subtrees(&self, subtrees: Vec<(PathBuf, Metadata)>) -> Vec<Subtree> {
+        let root_dev = self.root_dev;
+        if self.threads <= 1 || subtrees.len() <= 1 {
+            return subtrees
+                .iter()
+                .map(|(path, mtd)| subtree_usage(root_dev, path, mtd))
+                .collect();
+        }
+        let next = AtomicUsize::new(0);
+        let done: Mutex<Vec<(usize, Subtree)>> = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(subtrees.len()) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let (path, mtd) = match subtrees.get(index) {
+                        Some(subtree) => subtree,
+                        None => break,
+                    };
+                    let subtree = subtree_usage(root_dev, path, mtd);
+                    done.lock().unwrap().push((index, subtree));
+                });
+            }
+        });
+        let mut done = done.into_inner().unwrap();
+        done.sort_by_key(|(index, _)| *index);
+        done.into_iter().map(|(_, subtree)| subtree).collect()
+    }
+
+    // usage of "subtree" within the whole tree : without the hard links already counted
+    fn merge(&mut self, subtree: Subtree) -> DiskUsage {
+        let mut usage = subtree.usage;
+        for (key, linked) in subtree.linked {
+            if !self.seen.insert(key) {
+                usage.apparent -= linked.apparent;
+                usage.allocated -= linked.allocated;
+            }
+        }
+        usage
+    }
+
+    fn other_fs(&self, mtd: &Metadata) -> bool {
+        self.root_dev.is_some_and(|root_dev| mtd.dev() != root_dev)
+    }
+
+    // usage of the inode of "mtd" alone, zero for a hard link already counted
+    fn own_usage(&mut self, mtd: &Metadata) -> DiskUsage {
+        if !mtd.is_dir() && mtd.nlink() > 1 && !self.seen.insert((mtd.dev(), mtd.ino())) {
+            return DiskUsage::default();
+        }
+        inode_usage(mtd)
+    }
+}
+
+// "entry" as found on disk, none if its metadata could not be read
+fn listed_item(entry: &mut FileTree) -> Option<Item<'_>> {
+    match entry {
+        // not a symlink : its own metadata is that of the directory
+        FileTree::DirNode(sub_dir) => {
+            let mtd = sub_dir.metadata.clone()?;
+            Some(Item::Listed(sub_dir, mtd))
+        }
+        other => other.metadata().cloned().map(Item::Own),
+    }
+}
+
+// usage of a directory which is not listed, and of everything below it
+fn subtree_usage(root_dev: Option<u64>, path: &Path, mtd: &Metadata) -> Subtree {
+    let mut subtree = Subtree::default();
+    add_subtree(&mut subtree, root_dev, path, mtd);
+    subtree
+}
+
+fn add_subtree(subtree: &mut Subtree, root_dev: Option<u64>, path: &Path, mtd: &Metadata) {
+    subtree.usage.add(inode_usage(mtd));
+    if root_dev.is_some_and(|root_dev| mtd.dev() != root_dev) {
+        return;
+    }
+    if let Ok(entry_set) = fs::read_dir(path) {
+        for dir_entry in entry_set.flatten() {
+            let mtd = match dir_entry.metadata() {
+                Ok(mtd) => mtd,
+                Err(_) => continue,
+            };
+            if mtd.is_dir() {
+                add_subtree(subtree, root_dev, &dir_entry.path(), &mtd);
+            } else if mtd.nlink() > 1 {
+                let usage = inode_usage(&mtd);
+                if subtree
+                    .linked
+                    .insert((mtd.dev(), mtd.ino()), usage)
+                    .is_none()
+                {
+                    subtree.usage.add(usage);
+                }
+            } else {
+                subtree.usage.add(inode_usage(&mtd));
+            }
+        }
+    }
+}
+
+fn inode_usage(mtd: &Metadata) -> DiskUsage {
+    DiskUsage {
+        apparent: mtd.len(),
+        allocated: mtd.blocks() * 512,
+    }
+}
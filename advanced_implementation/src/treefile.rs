@@ -0,0 +1,943 @@
+//  in-memory model of the directory tree, built by walk()
+//  and consumed by the printer in treelibs.rs
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+
+use crate::treeconfig::CycleMode;
+use crate::treeconfig::DirsOrder;
+use crate::treeconfig::SizeKind;
+use crate::treeconfig::SortMode;
+use crate::treeconfig::TreeConfig;
+use crate::treedu::fill_disk_usage;
+use crate::treeignore::IgnoreStack;
+use crate::treepool::Prefetcher;
+
+#[derive(Debug)]
+pub enum FileTree {
+    DirNode(Directory),
+    FileNode(File),
+    LinkNode(Symlink),
+}
+
+/// What is known about the contents of a directory
+#[derive(Debug)]
+pub enum DirStatus {
+    /// entries have been read
+    Read,
+    /// entries have not been read, because of -L
+    NotRead,
+    /// entries have not been kept, because their number exceeded --filelimit
+    Exceeded(usize),
+    /// the directory could not be opened
+    Error(io::Error),
+    /// entries have not been read, because the directory is on another filesystem than the base (-x)
+    MountPoint,
+}
+
+#[derive(Debug)]
+pub struct Directory {
+    pub path: PathBuf,
+    pub metadata: Option<fs::Metadata>,
+    pub entries: Vec<FileTree>,
+    pub status: DirStatus,
+    /// size of the whole subtree on disk, with --du
+    pub du: Option<DiskUsage>,
+    /// entries left out with --top, the smallest ones
+    pub omitted: Option<Omitted>,
+}
+
+/// Entries of a directory which are not listed because of --top
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Omitted {
+    pub entries: usize,
+    /// their total size, as compared by --top
+    pub bytes: u64,
+}
+
+/// Sizes of a subtree, as du counts them
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiskUsage {
+    /// sum of the file lengths
+    pub apparent: u64,
+    /// sum of the allocated blocks, in bytes
+    pub allocated: u64,
+}
+
+impl DiskUsage {
+    pub fn add(&mut self, other: DiskUsage) {
+        self.apparent += other.apparent;
+        self.allocated += other.allocated;
+    }
+}
+
+#[derive(Debug)]
+pub struct File {
+    pub path: PathBuf,
+    /// metadata of the file, or the error met reading it
+    pub metadata: io::Result<fs::Metadata>,
+}
+
+#[derive(Debug)]
+pub struct Symlink {
+    pub path: PathBuf,
+    /// metadata of the link itself
+    pub metadata: Option<fs::Metadata>,
+    /// where the link points to, as written in the link
+    pub target: Option<PathBuf>,
+    /// metadata of the target, None if the link is dangling
+    pub target_metadata: Option<fs::Metadata>,
+    /// contents of the target directory, if the link was followed
+    pub expanded: Option<Box<Directory>>,
+    /// the link was not followed because it would close a cycle
+    pub cycle: bool,
+    /// the link was not followed because its target was already listed elsewhere in the tree
+    pub revisit: bool,
+}
+
+impl DirStatus {
+    /// why the entries of a directory are not listed, if so
+    pub fn message(&self) -> Option<String> {
+        match self {
+            DirStatus::Exceeded(num_entries) => Some(format!(
+                "{} entries exceeded filelimit, not printing dir",
+                num_entries
+            )),
+            DirStatus::Error(_) => Some("error opening dir".to_string()),
+            DirStatus::MountPoint => Some("mount point, will not expand it".to_string()),
+            DirStatus::Read | DirStatus::NotRead => None,
+        }
+    }
+}
+
+impl Symlink {
+    /// why a link to a directory was not followed, if so
+    pub fn not_followed(&self) -> Option<&'static str> {
+        if self.cycle {
+            Some("symlink cycle detected, will not expand it")
+        } else if self.revisit {
+            Some("directory already listed, will not expand it")
+        } else {
+            None
+        }
+    }
+}
+
+impl FileTree {
+    pub fn path(&self) -> &Path {
+        match self {
+            FileTree::DirNode(dir) => &dir.path,
+            FileTree::FileNode(file) => &file.path,
+            FileTree::LinkNode(link) => &link.path,
+        }
+    }
+
+    /// metadata of the entry itself, not following symlinks
+    pub fn metadata(&self) -> Option<&fs::Metadata> {
+        match self {
+            FileTree::DirNode(dir) => dir.metadata.as_ref(),
+            FileTree::FileNode(file) => file.metadata.as_ref().ok(),
+            FileTree::LinkNode(link) => link.metadata.as_ref(),
+        }
+    }
+
+    /// size of the entry as chosen by "kind", the whole subtree for a directory with --du
+    pub fn size(&self, kind: SizeKind) -> u64 {
+        let mtd = match self.metadata() {
+            Some(mtd) => mtd,
+            None => return 0,
+        };
+        let du = self.contents().and_then(|dir| dir.du);
+        match (kind, du) {
+            (SizeKind::Length, _) => mtd.len(),
+            (SizeKind::Apparent, Some(du)) => du.apparent,
+            (SizeKind::Allocated, Some(du)) => du.allocated,
+            (SizeKind::Apparent, None) => mtd.len(),
+            (SizeKind::Allocated, None) => mtd.blocks() * 512,
+        }
+    }
+
+    /// directory, or symlink pointing to a directory
+    pub fn is_dir(&self) -> bool {
+        match self {
+            FileTree::DirNode(_) => true,
+            FileTree::FileNode(_) => false,
+            FileTree::LinkNode(link) => link.target_metadata.as_ref().is_some_and(|m| m.is_dir()),
+        }
+    }
+
+    /// directory whose entries are part of the tree, if any
+    pub fn contents(&self) -> Option<&Directory> {
+        match self {
+            FileTree::DirNode(dir) => Some(dir),
+            FileTree::FileNode(_) => None,
+            FileTree::LinkNode(link) => link.expanded.as_deref(),
+        }
+    }
+
+    pub(crate) fn contents_mut(&mut self) -> Option<&mut Directory> {
+        match self {
+            FileTree::DirNode(dir) => Some(dir),
+            FileTree::FileNode(_) => None,
+            FileTree::LinkNode(link) => link.expanded.as_deref_mut(),
+        }
+    }
+}
+
+/// Totals of the entries of a tree, its root excluded
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Summary {
+    pub directories: usize,
+    pub files: usize,
+    /// symlinks, also counted in directories or files
+    pub links: usize,
+    /// total size of the entries, symlinks not followed
+    pub bytes: u64,
+    /// directories which could not be opened, and entries whose metadata could not be read
+    pub errors: usize,
+}
+
+impl Summary {
+    /// count "entry" alone, without its contents
+    pub fn of_entry(entry: &FileTree) -> Summary {
+        let mut summary = Summary::default();
+        if entry.is_dir() {
+            summary.directories += 1;
+        } else {
+            summary.files += 1;
+        }
+        if let FileTree::LinkNode(_) = entry {
+            summary.links += 1;
+        }
+        summary.bytes += entry.metadata().map_or(0, |m| m.len());
+        if let FileTree::FileNode(File {
+            metadata: Err(_), ..
+        }) = entry
+        {
+            summary.errors += 1;
+        }
+        summary
+    }
+
+    pub fn add(&mut self, other: Summary) {
+        self.directories += other.directories;
+        self.files += other.files;
+        self.links += other.links;
+        self.bytes += other.bytes;
+        self.errors += other.errors;
+    }
+}
+
+impl Directory {
+    fn new(path: PathBuf, metadata: Option<fs::Metadata>) -> Self {
+        Directory {
+            path,
+            metadata,
+            entries: Vec::new(),
+            status: DirStatus::NotRead,
+            du: None,
+            omitted: None,
+        }
+    }
+
+    /// count the entries of this directory and of all its sub-directories
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        if let DirStatus::Error(_) = self.status {
+            summary.errors += 1;
+        }
+        for entry in &self.entries {
+            summary.add(Summary::of_entry(entry));
+            if let Some(sub_dir) = entry.contents() {
+                summary.add(sub_dir.summary());
+            }
+        }
+        summary
+    }
+
+    /// errors met in this directory and in all its sub-directories
+    pub fn errors(&self) -> Vec<(&Path, &io::Error)> {
+        let mut errors = Vec::new();
+        if let DirStatus::Error(err) = &self.status {
+            errors.push((self.path.as_path(), err));
+        }
+        for entry in &self.entries {
+            if let FileTree::FileNode(File {
+                path,
+                metadata: Err(err),
+            }) = entry
+            {
+                errors.push((path.as_path(), err));
+            }
+            if let Some(sub_dir) = entry.contents() {
+                errors.append(&mut sub_dir.errors());
+            }
+        }
+        errors
+    }
+
+    /// sort the entries of this directory and of all its sub-directories
+    pub fn sort_by(&mut self, compare: fn(a: &FileTree, b: &FileTree) -> Ordering) {
+        self.entries.sort_by(compare);
+        for entry in &mut self.entries {
+            if let Some(sub_dir) = entry.contents_mut() {
+                sub_dir.sort_by(compare);
+            }
+        }
+    }
+}
+
+pub fn sort_by_name(a: &FileTree, b: &FileTree) -> Ordering {
+    a.path().file_name().cmp(&b.path().file_name())
+}
+
+pub fn sort_by_version(a: &FileTree, b: &FileTree) -> Ordering {
+    let a_name = a.path().file_name().unwrap_or_default().to_string_lossy();
+    let b_name = b.path().file_name().unwrap_or_default().to_string_lossy();
+    version_cmp(&a_name, &b_name).then_with(|| sort_by_name(a, b))
+}
+
+// largest first
+pub fn sort_by_size(a: &FileTree, b: &FileTree) -> Ordering {
+    let a_size = a.metadata().map_or(0, |m| m.len());
+    let b_size = b.metadata().map_or(0, |m| m.len());
+    b_size.cmp(&a_size).then_with(|| sort_by_name(a, b))
+}
+
+// oldest first
+pub fn sort_by_mtime(a: &FileTree, b: &FileTree) -> Ordering {
+    let a_time = a.metadata().map_or((0, 0), |m| (m.mtime(), m.mtime_nsec()));
+    let b_time = b.metadata().map_or((0, 0), |m| (m.mtime(), m.mtime_nsec()));
+    a_time.cmp(&b_time).then_with(|| sort_by_name(a, b))
+}
+
+// oldest first
+pub fn sort_by_ctime(a: &FileTree, b: &FileTree) -> Ordering {
+    let a_time = a.metadata().map_or((0, 0), |m| (m.ctime(), m.ctime_nsec()));
+    let b_time = b.metadata().map_or((0, 0), |m| (m.ctime(), m.ctime_nsec()));
+    a_time.cmp(&b_time).then_with(|| sort_by_name(a, b))
+}
+
+// natural order of names : runs of digits are compared by their numerical value
+fn version_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_num = take_number(&mut a_chars);
+                let b_num = take_number(&mut b_chars);
+                // leading zeros removed, a longer number is a larger one
+                let ordering = a_num
+                    .len()
+                    .cmp(&b_num.len())
+                    .then_with(|| a_num.cmp(&b_num));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        number.push(c);
+    }
+    number.trim_start_matches('0').to_string()
+}
+
+// sort the entries of a single directory, as configured by cfg
+fn sort_entries(entries: &mut [FileTree], cfg: &TreeConfig) {
+    let compare: Option<fn(a: &FileTree, b: &FileTree) -> Ordering> = match cfg.sort {
+        SortMode::Name => Some(sort_by_name),
+        SortMode::Version => Some(sort_by_version),
+        SortMode::Size => Some(sort_by_size),
+        SortMode::Mtime => Some(sort_by_mtime),
+        SortMode::Ctime => Some(sort_by_ctime),
+        SortMode::None => None,
+    };
+    if cfg.sort == SortMode::Size && cfg.du != SizeKind::Length {
+        // largest first, by the --du totals once they are known
+        entries.sort_by(|a, b| {
+            b.size(cfg.du)
+                .cmp(&a.size(cfg.du))
+                .then_with(|| sort_by_name(a, b))
+        });
+        if cfg.reverse {
+            entries.reverse();
+        }
+    } else if let Some(compare) = compare {
+        entries.sort_by(compare);
+        if cfg.reverse {
+            entries.reverse();
+        }
+    }
+    // stable sorts : the order within each group is kept
+    match cfg.dirs_order {
+        DirsOrder::DirsFirst => entries.sort_by_key(|e| !e.is_dir()),
+        DirsOrder::FilesFirst => entries.sort_by_key(|e| e.is_dir()),
+        DirsOrder::Mixed => {}
+    }
+}
+
+//  walk the tree rooted in "root", as configured by "cfg"
+//  a root which is not a directory is returned as a single node
+//  errors met below the root are kept in the tree, only a missing root is an error
+pub fn walk(root: &Path, cfg: &TreeConfig) -> io::Result<FileTree> {
+    let mut tree = base(root)?;
+    let mut walker = Walker::new(root, cfg);
+    let root_dev = walker.root_dev;
+    let directory = match tree.contents_mut() {
+        Some(directory) => directory,
+        None => return Ok(tree),
+    };
+    if cfg.threads > 1 {
+        let prefetcher = Prefetcher::new(cfg, root, cfg.threads);
+        thread::scope(|scope| {
+            for index in 0..prefetcher.workers() {
+                let prefetcher = &prefetcher;
+                scope.spawn(move || prefetcher.run_worker(index));
+            }
+            walker.prefetcher = Some(&prefetcher);
+            walker.walk_dir(directory, 0, false);
+            prefetcher.finish();
+        });
+    } else {
+        walker.walk_dir(directory, 0, false);
+    }
+    if cfg.du != SizeKind::Length {
+        fill_disk_usage(&mut tree, root_dev, cfg);
+    }
+    if let Some(directory) = tree.contents_mut() {
+        if cfg.du != SizeKind::Length || cfg.top != 0 {
+            rank_entries(directory, cfg);
+        }
+    }
+    Ok(tree)
+}
+
+//  once the --du totals are known : sort by them again, and keep the --top largest entries
+//  of every directory, in the order they are sorted in
+fn rank_entries(directory: &mut Directory, cfg: &TreeConfig) {
+    if cfg.du != SizeKind::Length && cfg.sort == SortMode::Size {
+        sort_entries(&mut directory.entries, cfg);
+    }
+    if cfg.top != 0 && directory.entries.len() > cfg.top {
+        let sizes: Vec<u64> = directory.entries.iter().map(|e| e.size(cfg.du)).collect();
+        let mut ranks: Vec<usize> = (0..sizes.len()).collect();
+        ranks.sort_by(|&a, &b| sizes[b].cmp(&sizes[a]).then(a.cmp(&b)));
+        let mut kept = vec![false; sizes.len()];
+        for &index in &ranks[..cfg.top] {
+            kept[index] = true;
+        }
+        let mut omitted = Omitted::default();
+        for &index in &ranks[cfg.top..] {
+            omitted.entries += 1;
+            omitted.bytes += sizes[index];
+        }
+        directory.omitted = Some(omitted);
+        let mut index = 0;
+        directory.entries.retain(|_| {
+            index += 1;
+            kept[index - 1]
+        });
+    }
+    for entry in &mut directory.entries {
+        if let Some(sub_dir) = entry.contents_mut() {
+            rank_entries(sub_dir, cfg);
+        }
+    }
+}
+
+//  walk the tree below "tree" as walk() does, handing each entry to "visitor" as soon as it is read :
+//  entries are neither sorted nor kept, memory does not grow with the number of files.
+//  directories are read in the walk itself, one after the other : --threads does not apply
+pub(crate) fn stream(
+    tree: &mut FileTree,
+    cfg: &TreeConfig,
+    visitor: &mut dyn Visitor,
+) -> io::Result<()> {
+    let root = tree.path().to_path_buf();
+    let mut walker = Walker::new(&root, cfg);
+    match tree.contents_mut() {
+        Some(directory) => walker.stream_dir(directory, 0, false, visitor),
+        None => Ok(()),
+    }
+}
+
+// node of the base of the tree, without any contents
+pub(crate) fn base(root: &Path) -> io::Result<FileTree> {
+    fs::symlink_metadata(root)?;
+    let mut tree = node_from_path(root.to_path_buf());
+    // a symlink given as base is always followed
+    if let FileTree::LinkNode(link) = &mut tree {
+        if link.target_metadata.as_ref().is_some_and(|m| m.is_dir()) {
+            link.expanded = Some(Box::new(Directory::new(
+                link.path.clone(),
+                link.target_metadata.clone(),
+            )));
+        }
+    }
+    Ok(tree)
+}
+
+/// receives the entries of a tree, in the order they are printed
+pub(crate) trait Visitor {
+    /// the directory whose entries follow, read or not
+    fn status(&mut self, directory: &Directory) -> io::Result<()>;
+    /// next entry of the current directory, "last" if no other entry follows it
+    fn entry(&mut self, node: &FileTree, last: bool) -> io::Result<()>;
+    /// the contents of the entry just visited follow, until leave()
+    fn enter(&mut self);
+    fn leave(&mut self);
+    /// entries of the current directory left out by --top, after its last entry
+    fn omitted(&mut self, omitted: &Omitted) -> io::Result<()>;
+}
+
+// hand the contents of "directory", already walked, to "visitor"
+pub(crate) fn replay(directory: &Directory, visitor: &mut dyn Visitor) -> io::Result<()> {
+    visitor.status(directory)?;
+    for (index, entry) in directory.entries.iter().enumerate() {
+        let last = index + 1 == directory.entries.len() && directory.omitted.is_none();
+        visitor.entry(entry, last)?;
+        if let Some(sub_dir) = entry.contents() {
+            visitor.enter();
+            replay(sub_dir, visitor)?;
+            visitor.leave();
+        }
+    }
+    match &directory.omitted {
+        Some(omitted) => visitor.omitted(omitted),
+        None => Ok(()),
+    }
+}
+
+// node of "path", with its metadata but without any contents
+fn node_from_path(path: PathBuf) -> FileTree {
+    match fs::symlink_metadata(&path) {
+        Ok(mtd) if mtd.file_type().is_symlink() => FileTree::LinkNode(Symlink {
+            target: fs::read_link(&path).ok(),
+            target_metadata: fs::metadata(&path).ok(),
+            path,
+            metadata: Some(mtd),
+            expanded: None,
+            cycle: false,
+            revisit: false,
+        }),
+        Ok(mtd) if mtd.is_dir() => FileTree::DirNode(Directory::new(path, Some(mtd))),
+        metadata => FileTree::FileNode(File { path, metadata }),
+    }
+}
+
+// nodes of the entries of the directory at "path", hidden ones only if cfg.show_hidden
+pub(crate) fn read_entries(path: &Path, cfg: &TreeConfig) -> io::Result<Vec<FileTree>> {
+    Ok(entries_of(path, cfg)?.collect())
+}
+
+// as read_entries, one node at a time
+fn entries_of<'a>(
+    path: &Path,
+    cfg: &'a TreeConfig,
+) -> io::Result<impl Iterator<Item = FileTree> + 'a> {
+    Ok(fs::read_dir(path)?
+        .filter_map(|v| v.ok())
+        .filter(|v| {
+            // by bytes : names which are not UTF-8 are listed too
+            cfg.show_hidden || v.file_name().as_bytes().first() != Some(&b'.')
+        })
+        .map(|v| node_from_path(v.path())))
+}
+
+// device of the base, if the walk stays on its filesystem
+pub(crate) fn root_dev(root: &Path, cfg: &TreeConfig) -> Option<u64> {
+    if !cfg.one_file_system {
+        return None;
+    }
+    fs::metadata(root).ok().map(|mtd| mtd.dev())
+}
+
+// identity of a directory, whatever the path it is reached by
+fn dir_key(mtd: &fs::Metadata) -> (u64, u64) {
+    (mtd.dev(), mtd.ino())
+}
+
+// state shared along the whole walk
+struct Walker<'a> {
+    cfg: &'a TreeConfig,
+    root: &'a Path,
+    /// directories from the base down to the one being walked, as (st_dev, st_ino)
+    ancestors: HashSet<(u64, u64)>,
+    /// every directory listed so far, or about to be as the entry of one walked, with -l
+    /// unless CycleMode::Fast
+    listed: HashSet<(u64, u64)>,
+    /// rules of the ignore files met so far, with --gitignore
+    ignore: Option<IgnoreStack>,
+    /// directories read ahead by other threads, with --threads
+    prefetcher: Option<&'a Prefetcher<'a>>,
+    /// device of the base, with -x
+    root_dev: Option<u64>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(root: &'a Path, cfg: &'a TreeConfig) -> Self {
+        let mut walker = Walker {
+            cfg,
+            root,
+            ancestors: HashSet::new(),
+            listed: HashSet::new(),
+            ignore: cfg.gitignore.then(|| IgnoreStack::new(root)),
+            prefetcher: None,
+            root_dev: root_dev(root, cfg),
+        };
+        // a link to a parent of the base would list the base again, inside itself
+        if cfg.cycle_mode == CycleMode::Advanced {
+            if let Ok(can_root) = fs::canonicalize(root) {
+                walker.ancestors.extend(
+                    can_root
+                        .ancestors()
+                        .skip(1)
+                        .filter_map(|dir| fs::metadata(dir).ok())
+                        .map(|mtd| dir_key(&mtd)),
+                );
+            }
+        }
+        walker
+    }
+
+    // should "node" be kept, according to -I and -P
+    // "match_all" : an ancestor directory matched -P with --matchdirs
+    fn keep(&self, node: &FileTree, match_all: bool) -> bool {
+        if let Some(ignore) = &self.ignore {
+            if ignore.is_ignored(node.path(), matches!(node, FileTree::DirNode(_))) {
+                return false;
+            }
+        }
+        let rel_path = node.path().strip_prefix(self.root).unwrap_or(node.path());
+        if self.cfg.exclude.iter().any(|p| p.matches_path(rel_path)) {
+            return false;
+        }
+        if match_all || self.cfg.include.is_empty() || node.is_dir() {
+            return true;
+        }
+        self.cfg.include.iter().any(|p| p.matches_path(rel_path))
+    }
+
+    // does "node" enable --matchdirs for its contents
+    fn matches_dir(&self, node: &FileTree) -> bool {
+        let rel_path = node.path().strip_prefix(self.root).unwrap_or(node.path());
+        self.cfg.match_dirs && self.cfg.include.iter().any(|p| p.matches_path(rel_path))
+    }
+
+    // fill the entries of "directory", which is at depth "depth" of the walk
+    fn walk_dir(&mut self, directory: &mut Directory, depth: usize, match_all: bool) {
+        let key = self.enter_dir(directory);
+        self.walk_entries(directory, depth, match_all);
+        self.leave_dir(key);
+    }
+
+    // add "directory" to the ancestors, returns its key if it was not among them yet
+    fn enter_dir(&mut self, directory: &Directory) -> Option<(u64, u64)> {
+        let key = match &directory.metadata {
+            Some(mtd) => Some(dir_key(mtd)),
+            None => fs::metadata(&directory.path).ok().map(|mtd| dir_key(&mtd)),
+        };
+        if let Some(key) = key.filter(|_| self.tracks_listed()) {
+            self.listed.insert(key);
+        }
+        // a key already among the ancestors (e.g. a bind mount) stays there for the callers
+        key.filter(|key| self.ancestors.insert(*key))
+    }
+
+    // is "listed" of any use : only links to directories, followed with -l, look it up
+    fn tracks_listed(&self) -> bool {
+        self.cfg.follow_symlink && self.cfg.cycle_mode != CycleMode::Fast
+    }
+
+    // the sub-directories among "entries" count as listed before any of them is walked :
+    // a directory is expanded where it is, not through a link to it sorted before it
+    fn list_ahead<'e>(&mut self, entries: impl Iterator<Item = &'e FileTree>) {
+        if !self.tracks_listed() {
+            return;
+        }
+        for entry in entries {
+            if let FileTree::DirNode(Directory {
+                metadata: Some(mtd),
+                ..
+            }) = entry
+            {
+                self.listed.insert(dir_key(mtd));
+            }
+        }
+    }
+
+    fn leave_dir(&mut self, key: Option<(u64, u64)>) {
+        if let Some(key) = key {
+            self.ancestors.remove(&key);
+        }
+    }
+
+    // is "directory" on another filesystem than the base, with -x
+    fn crosses_mount(&self, directory: &Directory) -> bool {
+        let root_dev = match self.root_dev {
+            Some(root_dev) => root_dev,
+            None => return false,
+        };
+        match &directory.metadata {
+            Some(mtd) => mtd.dev() != root_dev,
+            None => fs::metadata(&directory.path).is_ok_and(|mtd| mtd.dev() != root_dev),
+        }
+    }
+
+    // should the walk descend into the target of "link", marks it if a cycle or a revisit prevents it
+    fn follow(&self, link: &mut Symlink) -> bool {
+        let target = match &link.target_metadata {
+            Some(mtd) if mtd.is_dir() && self.cfg.follow_symlink => dir_key(mtd),
+            _ => return false,
+        };
+        // a target among the ancestors would be listed inside itself, forever
+        if self.ancestors.contains(&target) {
+            link.cycle = true;
+            return false;
+        }
+        if self.listed.contains(&target) {
+            link.revisit = true;
+            return false;
+        }
+        true
+    }
+
+    fn walk_entries(&mut self, directory: &mut Directory, depth: usize, match_all: bool) {
+        let cfg = self.cfg;
+        // cfg.level == 0 -> go all the way
+        // cfg.level != 0 -> go only to depth==cfg.level
+        if (cfg.level != 0) && (depth == cfg.level) {
+            return;
+        }
+        if self.crosses_mount(directory) {
+            directory.status = DirStatus::MountPoint;
+            return;
+        }
+        // get elements in this directory
+        let entry_set = match self.prefetcher {
+            Some(prefetcher) => prefetcher.take(&directory.path),
+            None => read_entries(&directory.path, cfg),
+        };
+        let entry_set = match entry_set {
+            Ok(entry_set) => entry_set,
+            Err(err) => {
+                directory.status = DirStatus::Error(err);
+                return;
+            }
+        };
+        let ignore_depth = self.ignore.as_mut().map(|i| i.push_dir(&directory.path));
+        let mut entries = entry_set
+            .into_iter()
+            .filter(|node| self.keep(node, match_all))
+            .collect::<Vec<_>>();
+        sort_entries(&mut entries, cfg);
+        directory.status = DirStatus::Read;
+        let num_entries: usize = entries.len();
+        // if current dir has too many entries, keep none
+        if (cfg.filelimit != 0) && num_entries > cfg.filelimit {
+            directory.status = DirStatus::Exceeded(num_entries);
+            self.pop_ignore(ignore_depth);
+            return;
+        }
+        if cfg.only_dir {
+            entries.retain(|x| x.is_dir());
+        }
+        // read ahead only the sub-directories walked below, none beyond the -L level
+        if let Some(prefetcher) = self.prefetcher {
+            if cfg.level == 0 || depth + 1 < cfg.level {
+                let paths = entries
+                    .iter()
+                    .filter_map(|entry| match entry {
+                        FileTree::DirNode(sub_dir) if !self.crosses_mount(sub_dir) => {
+                            Some(sub_dir.path.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                prefetcher.prefetch(paths, depth + 1);
+            }
+        }
+        self.list_ahead(entries.iter());
+        for entry in &mut entries {
+            let match_all_new = match_all || self.matches_dir(entry);
+            match entry {
+                FileTree::DirNode(sub_dir) => self.walk_dir(sub_dir, depth + 1, match_all_new),
+                FileTree::LinkNode(link) => {
+                    if !self.follow(link) {
+                        continue;
+                    }
+                    let mut target_dir =
+                        Directory::new(link.path.clone(), link.target_metadata.clone());
+                    self.walk_dir(&mut target_dir, depth + 1, match_all_new);
+                    link.expanded = Some(Box::new(target_dir));
+                }
+                FileTree::FileNode(_) => {}
+            }
+        }
+        if cfg.prune {
+            // their own empty sub-directories are already pruned
+            entries.retain(|entry| {
+                !entry.contents().is_some_and(|sub_dir| {
+                    matches!(sub_dir.status, DirStatus::Read) && sub_dir.entries.is_empty()
+                })
+            });
+        }
+        directory.entries = entries;
+        self.pop_ignore(ignore_depth);
+    }
+
+    fn stream_dir(
+        &mut self,
+        directory: &mut Directory,
+        depth: usize,
+        match_all: bool,
+        visitor: &mut dyn Visitor,
+    ) -> io::Result<()> {
+        let key = self.enter_dir(directory);
+        let result = self.stream_entries(directory, depth, match_all, visitor);
+        self.leave_dir(key);
+        result
+    }
+
+    // as walk_entries, handing each entry to "visitor" instead of keeping it in "directory"
+    fn stream_entries(
+        &mut self,
+        directory: &mut Directory,
+        depth: usize,
+        match_all: bool,
+        visitor: &mut dyn Visitor,
+    ) -> io::Result<()> {
+        let cfg = self.cfg;
+        if (cfg.level != 0) && (depth == cfg.level) {
+            return Ok(());
+        }
+        if self.crosses_mount(directory) {
+            directory.status = DirStatus::MountPoint;
+            return visitor.status(directory);
+        }
+        let mut entry_set = match entries_of(&directory.path, cfg) {
+            Ok(entry_set) => entry_set,
+            Err(err) => {
+                directory.status = DirStatus::Error(err);
+                return visitor.status(directory);
+            }
+        };
+        let ignore_depth = self.ignore.as_mut().map(|i| i.push_dir(&directory.path));
+        directory.status = DirStatus::Read;
+        // with a filelimit, the directory is read twice : once to count, once to print
+        if cfg.filelimit != 0 {
+            let num_entries = entries_of(&directory.path, cfg)
+                .map(|entries| entries.filter(|node| self.keep(node, match_all)).count())
+                .unwrap_or(0);
+            if num_entries > cfg.filelimit {
+                directory.status = DirStatus::Exceeded(num_entries);
+                self.pop_ignore(ignore_depth);
+                return visitor.status(directory);
+            }
+        }
+        // with -l, one more pass lists the sub-directories ahead, as walk_entries does
+        if self.tracks_listed() {
+            if let Ok(entries) = entries_of(&directory.path, cfg) {
+                let sub_dirs: Vec<_> = entries
+                    .filter(|node| {
+                        matches!(node, FileTree::DirNode(_)) && self.keep(node, match_all)
+                    })
+                    .collect();
+                self.list_ahead(sub_dirs.iter());
+            }
+        }
+        // one entry ahead, to know which one is the last
+        let mut next = self.next_entry(&mut entry_set, match_all);
+        while let Some(mut entry) = next {
+            next = self.next_entry(&mut entry_set, match_all);
+            let match_all_new = match_all || self.matches_dir(&entry);
+            let follow = match &mut entry {
+                FileTree::LinkNode(link) => self.follow(link),
+                _ => false,
+            };
+            visitor.entry(&entry, next.is_none())?;
+            let sub_dir = match &mut entry {
+                FileTree::DirNode(sub_dir) => sub_dir,
+                FileTree::LinkNode(link) if follow => link.expanded.insert(Box::new(
+                    Directory::new(link.path.clone(), link.target_metadata.clone()),
+                )),
+                _ => continue,
+            };
+            visitor.enter();
+            self.stream_dir(sub_dir, depth + 1, match_all_new, visitor)?;
+            visitor.leave();
+        }
+        self.pop_ignore(ignore_depth);
+        Ok(())
+    }
+
+    // next entry of "entry_set" to be listed
+    fn next_entry(
+        &self,
+        entry_set: &mut impl Iterator<Item = FileTree>,
+        match_all: bool,
+    ) -> Option<FileTree> {
+        entry_set.find(|node| self.keep(node, match_all) && (!self.cfg.only_dir || node.is_dir()))
+    }
+
+    // forget the ignore files of a directory, once its walk is done
+    fn pop_ignore(&mut self, ignore_depth: Option<usize>) {
+        if let (Some(ignore), Some(depth)) = (self.ignore.as_mut(), ignore_depth) {
+            ignore.pop_to(depth);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::version_cmp;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn numbers_by_value() {
+        assert_eq!(version_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(version_cmp("file10", "file2"), Ordering::Greater);
+        assert_eq!(version_cmp("v1.9.3", "v1.10.0"), Ordering::Less);
+        assert_eq!(version_cmp("a100b2", "a100b11"), Ordering::Less);
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(version_cmp("file007", "file10"), Ordering::Less);
+        assert_eq!(version_cmp("file010", "file9"), Ordering::Greater);
+        assert_eq!(version_cmp("file01", "file1"), Ordering::Equal);
+        assert_eq!(version_cmp("file0", "file00"), Ordering::Equal);
+    }
+
+    #[test]
+    fn text_and_prefixes() {
+        assert_eq!(version_cmp("abc", "abd"), Ordering::Less);
+        assert_eq!(version_cmp("file", "file1"), Ordering::Less);
+        assert_eq!(version_cmp("file1a", "file1"), Ordering::Greater);
+        assert_eq!(version_cmp("B", "a"), Ordering::Less);
+        assert_eq!(version_cmp("same", "same"), Ordering::Equal);
+    }
+}
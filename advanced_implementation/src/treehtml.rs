@@ -0,0 +1,280 @@
+//  HTML printer of the model, for -H baseHREF :
+//  a self-contained page, each entry is a hyperlink rooted in baseHREF,
+//  colored with the same classes as color_output in treelibs.rs.
+//  with --collapsible every directory is a <details> node, which can be folded
+
+use std::fmt::Write as _;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::treeconfig::Charset;
+use crate::treeconfig::PrintConfig;
+use crate::treefile::Directory;
+use crate::treefile::FileTree;
+use crate::treelibs::entry_text;
+use crate::treelibs::info_column;
+use crate::treelibs::is_executable;
+use crate::treelibs::my_write;
+use crate::treelibs::omitted_line;
+use crate::treelibs::report_line;
+use crate::treelibs::trees_summary;
+use crate::treelibs::Lines;
+use crate::treelibs::NO_INDENT;
+
+const STYLE: &str = "    body { font-family: monospace; }
+    .tree { white-space: pre; }
+    .tree a { text-decoration: none; }
+    .tree details > summary { list-style: none; cursor: pointer; }
+    .tree details > summary::-webkit-details-marker { display: none; }
+    .dir { color: #b58900; }
+    .exec { color: #859900; }
+    .file { color: #d33682; }
+    .link { color: #2aa198; }
+    .orphan { color: #dc322f; }
+    .report { margin-top: 1em; }";
+
+pub(crate) fn print_html(
+    outfile: &mut dyn std::io::Write,
+    trees: &[FileTree],
+    cfg: &PrintConfig,
+    keep_canonical: bool, //  for the base only
+    full_rel_path: bool,  //  for the base only
+) -> io::Result<()> {
+    let mut text = String::new();
+    let _ = write!(
+        text,
+        "<!DOCTYPE html>\n<html>\n<head>\n  <meta charset=\"utf-8\">\n  <title>{}</title>\n  <style>\n{}\n  </style>\n</head>\n<body>\n  <h1>{}</h1>\n  <div class=\"tree\">",
+        escape(&cfg.html_title),
+        STYLE,
+        escape(&cfg.html_title)
+    );
+    // the graphics mode of VT100 terminals means nothing to a browser
+    let charset = match cfg.charset {
+        Charset::Vt100 => Charset::Utf8,
+        charset => charset,
+    };
+    // escaped once here, prefixes included : a custom charset may hold '<' or '&'
+    let lines = Lines::new(charset, cfg.indent);
+    let lines = Lines {
+        other_child: escape(&lines.other_child),
+        other_entry: escape(&lines.other_entry),
+        final_child: escape(&lines.final_child),
+        final_entry: escape(&lines.final_entry),
+    };
+    for tree in trees {
+        let root = Root::new(tree.path(), trees.len() > 1);
+        let base_line = format!(
+            "{}{}",
+            escape(&info_column(tree, cfg)),
+            link(tree, &root, cfg, keep_canonical, full_rel_path)
+        );
+        match tree.contents() {
+            Some(dir) => write_dir(&mut text, base_line, dir, &root, "", &lines, cfg),
+            None => {
+                let _ = write!(
+                    text,
+                    "{}\n{}{}[given base is not a directory]\n",
+                    base_line, lines.final_child, lines.final_entry
+                );
+            }
+        }
+    }
+    text.push_str("</div>\n");
+    if !cfg.noreport {
+        let summary = trees_summary(trees, cfg);
+        let _ = writeln!(
+            text,
+            "  <p class=\"report\">{}</p>",
+            escape(&report_line(&summary, cfg))
+        );
+    }
+    text.push_str("</body>\n</html>");
+    my_write(outfile, &text)
+}
+
+// root of a tree, and the path its entries are linked under, relative to baseHREF
+struct Root<'a> {
+    path: &'a Path,
+    href: PathBuf,
+}
+
+impl<'a> Root<'a> {
+    // with several roots, the links of each one go through its path as given,
+    // "/" and "." aside, so that they do not collide
+    fn new(path: &'a Path, several: bool) -> Self {
+        let href = if several {
+            path.components()
+                .filter(|c| matches!(c, Component::Normal(_) | Component::ParentDir))
+                .collect()
+        } else {
+            PathBuf::new()
+        };
+        Root { path, href }
+    }
+}
+
+// the line of a directory, followed by its entries
+fn write_dir(
+    text: &mut String,
+    line: String,
+    dir: &Directory,
+    root: &Root,
+    prefix: &str,
+    lines: &Lines,
+    cfg: &PrintConfig,
+) {
+    if cfg.html_collapsible {
+        let _ = write!(text, "<details open><summary>{}</summary>", line);
+    } else {
+        let _ = writeln!(text, "{}", line);
+    }
+    match dir.status.message() {
+        Some(message) => {
+            let _ = writeln!(text, "{}{}[{}]", prefix, lines.final_entry, message);
+        }
+        None => {
+            for (index, entry) in dir.entries.iter().enumerate() {
+                let is_last = index == dir.entries.len() - 1 && dir.omitted.is_none();
+                let (entry_to_use, child_to_use) = if cfg.no_indent {
+                    (NO_INDENT, NO_INDENT)
+                } else if is_last {
+                    (lines.final_entry.as_str(), lines.final_child.as_str())
+                } else {
+                    (lines.other_entry.as_str(), lines.other_child.as_str())
+                };
+                let line = format!(
+                    "{}{}{}{}",
+                    prefix,
+                    entry_to_use,
+                    escape(&info_column(entry, cfg)),
+                    link(entry, root, cfg, cfg.keep_canonical, cfg.full_rel_path)
+                );
+                let prefix_new = prefix.to_string() + child_to_use;
+                match entry.contents() {
+                    Some(sub_dir) => write_dir(text, line, sub_dir, root, &prefix_new, lines, cfg),
+                    None => {
+                        let _ = writeln!(text, "{}", line);
+                        if let FileTree::LinkNode(link) = entry {
+                            if let Some(reason) = link.not_followed() {
+                                let _ = writeln!(
+                                    text,
+                                    "{}{}[{}]",
+                                    prefix_new, lines.final_entry, reason
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            if let Some(omitted) = &dir.omitted {
+                let entry_to_use = if cfg.no_indent {
+                    NO_INDENT
+                } else {
+                    lines.final_entry.as_str()
+                };
+                let _ = writeln!(
+                    text,
+                    "{}{}{}",
+                    prefix,
+                    entry_to_use,
+                    escape(&omitted_line(omitted, cfg))
+                );
+            }
+        }
+    }
+    if cfg.html_collapsible {
+        text.push_str("</details>");
+    }
+}
+
+// hyperlink to "node", colored as color_output would
+fn link(
+    node: &FileTree,
+    root: &Root,
+    cfg: &PrintConfig,
+    keep_canonical: bool,
+    full_rel_path: bool,
+) -> String {
+    let (filename, symlink) = entry_text(node, keep_canonical, full_rel_path, cfg.names);
+    let rel_path = node.path().strip_prefix(root.path).unwrap_or(node.path());
+    // join() would end the root itself with a '/', added below for directories
+    let rel_path = if rel_path.as_os_str().is_empty() {
+        root.href.clone()
+    } else {
+        root.href.join(rel_path)
+    };
+    let mut href = cfg.html_base.trim_end_matches('/').to_string();
+    if !rel_path.as_os_str().is_empty() {
+        href.push('/');
+        href.push_str(&url_encode(&rel_path));
+    }
+    if node.is_dir() {
+        href.push('/');
+    }
+    let (name_class, target_class) = match node {
+        // the target is classed by its own metadata, as paint_target does
+        FileTree::LinkNode(link) => match &link.target_metadata {
+            None => ("orphan", None),
+            Some(mtd) if mtd.is_dir() => ("link", Some("dir")),
+            Some(mtd) if mtd.permissions().mode() & 0o111 != 0 => ("link", Some("exec")),
+            Some(_) => ("link", Some("file")),
+        },
+        _ if node.is_dir() => ("dir", None),
+        _ if is_executable(node) => ("exec", None),
+        _ => ("file", None),
+    };
+    let mut anchor = format!(
+        "<a class=\"{}\" href=\"{}\">{}</a>",
+        name_class,
+        escape(&href),
+        escape(&filename)
+    );
+    if !symlink.is_empty() {
+        match target_class {
+            Some(class) => {
+                let _ = write!(
+                    anchor,
+                    " -&gt; <span class=\"{}\">{}</span>",
+                    class,
+                    escape(&symlink)
+                );
+            }
+            None => {
+                let _ = write!(anchor, " -&gt; {}", escape(&symlink));
+            }
+        }
+    }
+    anchor
+}
+
+// percent-encoding of a relative path, '/' kept as separator
+fn url_encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
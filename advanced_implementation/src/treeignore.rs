@@ -0,0 +1,294 @@
+//  .gitignore support for --gitignore
+//  rules are read from, by increasing precedence :
+//      -   the global excludes file (core.excludesFile, or ~/.config/git/ignore)
+//      -   .git/info/exclude of the repository
+//      -   .gitignore then .ignore of every directory from the repository root down to the entry
+//  the last matching rule wins, a "!pattern" rule re-includes what a previous one excluded.
+//  all paths are kept relative to the repository root (or to the root of the tree, out of a repository)
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::treepattern::Pattern;
+
+#[derive(Debug)]
+struct IgnoreRule {
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    /// pattern contains a '/' : matched against the path relative to the ignore file
+    anchored: bool,
+}
+
+#[derive(Debug)]
+struct IgnoreFile {
+    /// directory of the ignore file, relative to the repository root
+    base: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+#[derive(Debug)]
+pub struct IgnoreStack {
+    /// root of the tree, as given to the walker
+    walk_root: PathBuf,
+    /// root of the tree, relative to the repository root
+    root_in_repo: PathBuf,
+    files: Vec<IgnoreFile>,
+}
+
+impl IgnoreStack {
+    // load the global rules, and those of the directories above "walk_root"
+    pub fn new(walk_root: &Path) -> Self {
+        let can_root = fs::canonicalize(walk_root).unwrap_or(walk_root.to_path_buf());
+        let repo_root = can_root
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .map(Path::to_path_buf);
+        let mut stack = IgnoreStack {
+            walk_root: walk_root.to_path_buf(),
+            root_in_repo: PathBuf::new(),
+            files: Vec::new(),
+        };
+        if let Some(path) = global_excludes_file() {
+            stack.load(&path, PathBuf::new());
+        }
+        if let Some(repo_root) = repo_root {
+            stack.root_in_repo = can_root
+                .strip_prefix(&repo_root)
+                .unwrap_or(Path::new(""))
+                .to_path_buf();
+            stack.load(&repo_root.join(".git/info/exclude"), PathBuf::new());
+            // directories from the repository root down to the root of the tree, excluded
+            let mut dir = repo_root;
+            let mut base = PathBuf::new();
+            for component in stack.root_in_repo.clone().components() {
+                stack.load_dir(&dir, &base);
+                dir.push(component);
+                base.push(component);
+            }
+        }
+        stack
+    }
+
+    // load the ignore files of "dir", a directory of the tree, returns the previous depth of the stack
+    pub fn push_dir(&mut self, dir: &Path) -> usize {
+        let depth = self.files.len();
+        let base = self.repo_path(dir);
+        self.load_dir(dir, &base);
+        depth
+    }
+
+    // forget the ignore files loaded after "depth"
+    pub fn pop_to(&mut self, depth: usize) {
+        self.files.truncate(depth);
+    }
+
+    // is the entry at "path" ignored
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let repo_path = self.repo_path(path);
+        for file in self.files.iter().rev() {
+            let rel_path = match repo_path.strip_prefix(&file.base) {
+                Ok(rel_path) => rel_path,
+                Err(_) => continue,
+            };
+            for rule in file.rules.iter().rev() {
+                if rule.dir_only && !is_dir {
+                    continue;
+                }
+                let matched = if rule.anchored {
+                    let components: Vec<_> = rel_path
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect();
+                    rule.pattern.matches(&components.join("/"))
+                } else {
+                    match rel_path.file_name() {
+                        Some(name) => rule.pattern.matches(&name.to_string_lossy()),
+                        None => false,
+                    }
+                };
+                if matched {
+                    return !rule.negated;
+                }
+            }
+        }
+        false
+    }
+
+    // path of an entry of the tree, relative to the repository root
+    fn repo_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.walk_root) {
+            Ok(rel_path) => self.root_in_repo.join(rel_path),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    fn load_dir(&mut self, dir: &Path, base: &Path) {
+        self.load(&dir.join(".gitignore"), base.to_path_buf());
+        self.load(&dir.join(".ignore"), base.to_path_buf());
+    }
+
+    fn load(&mut self, path: &Path, base: PathBuf) {
+        if let Ok(text) = fs::read_to_string(path) {
+            let rules: Vec<IgnoreRule> = text.lines().filter_map(parse_rule).collect();
+            if !rules.is_empty() {
+                self.files.push(IgnoreFile { base, rules });
+            }
+        }
+    }
+}
+
+fn parse_rule(line: &str) -> Option<IgnoreRule> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    // trailing spaces are ignored, unless escaped
+    let mut line = line.trim_end_matches(' ').to_string();
+    if line.ends_with('\\') {
+        line.push(' ');
+    }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.as_str()),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    if line.is_empty() {
+        return None;
+    }
+    let anchored = line.contains('/');
+    let line = line.strip_prefix('/').unwrap_or(line);
+    // '|' is no alternation in ignore files
+    let pattern = Pattern::new(&line.replace('|', "\\|"), false);
+    Some(IgnoreRule {
+        pattern,
+        negated,
+        dir_only,
+        anchored,
+    })
+}
+
+// core.excludesFile from the user git config, or its default location
+fn global_excludes_file() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|h| h.join(".config")));
+    let mut configs = Vec::new();
+    if let Some(config_home) = &config_home {
+        configs.push(config_home.join("git/config"));
+    }
+    if let Some(home) = &home {
+        configs.push(home.join(".gitconfig"));
+    }
+    // later config files win
+    let mut excludes_file = None;
+    for config in configs {
+        if let Some(value) = read_excludes_file(&config) {
+            excludes_file = Some(value);
+        }
+    }
+    match excludes_file {
+        Some(value) => match (value.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(value)),
+        },
+        None => config_home.map(|c| c.join("git/ignore")),
+    }
+}
+
+fn read_excludes_file(config: &Path) -> Option<String> {
+    let text = fs::read_to_string(config).ok()?;
+    let mut in_core = false;
+    let mut value = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_core = line.eq_ignore_ascii_case("[core]");
+        } else if in_core {
+            if let Some((key, val)) = line.split_once('=') {
+                if key.trim().eq_ignore_ascii_case("excludesfile") {
+                    value = Some(val.trim().trim_matches('"').to_string());
+                }
+            }
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_rule;
+    use super::IgnoreStack;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn rules() {
+        assert!(parse_rule("").is_none());
+        assert!(parse_rule("# comment").is_none());
+        assert!(parse_rule("!").is_none());
+        let rule = parse_rule("!build/").unwrap();
+        assert!(rule.negated && rule.dir_only && !rule.anchored);
+        assert!(rule.pattern.matches("build"));
+        let rule = parse_rule("/top.txt").unwrap();
+        assert!(rule.anchored && !rule.negated && !rule.dir_only);
+        assert!(rule.pattern.matches("top.txt"));
+        assert!(parse_rule("docs/*.md").unwrap().anchored);
+        assert!(parse_rule("*.o  ").unwrap().pattern.matches("a.o"));
+        assert!(parse_rule("a\\ ").unwrap().pattern.matches("a "));
+        assert!(parse_rule("a|b").unwrap().pattern.matches("a|b"));
+        assert!(parse_rule("x.log\r").unwrap().pattern.matches("x.log"));
+    }
+
+    #[test]
+    fn ignored() {
+        let root = std::env::temp_dir().join(format!("treeignore-test-{}", std::process::id()));
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join(".git/info/exclude"), "*.log\nbuild/\n").unwrap();
+        fs::write(
+            root.join(".gitignore"),
+            "!important.log\n/top.txt\ndocs/*.md\n*.tmp\n!keep.tmp\n",
+        )
+        .unwrap();
+        fs::write(root.join("sub/.gitignore"), "!*.tmp\n").unwrap();
+        // as new() would, without the global excludes file of the user
+        let mut stack = IgnoreStack {
+            walk_root: root.clone(),
+            root_in_repo: PathBuf::new(),
+            files: Vec::new(),
+        };
+        stack.load(&root.join(".git/info/exclude"), PathBuf::new());
+        stack.push_dir(&root);
+        let ignored = |stack: &IgnoreStack, path: &str, is_dir: bool| {
+            stack.is_ignored(&root.join(path), is_dir)
+        };
+        assert!(ignored(&stack, "a.log", false));
+        assert!(ignored(&stack, "sub/b.log", false));
+        // .gitignore over info/exclude
+        assert!(!ignored(&stack, "important.log", false));
+        assert!(ignored(&stack, "build", true));
+        assert!(!ignored(&stack, "build", false));
+        assert!(ignored(&stack, "top.txt", false));
+        assert!(!ignored(&stack, "sub/top.txt", false));
+        assert!(ignored(&stack, "docs/x.md", false));
+        assert!(!ignored(&stack, "docs/sub/x.md", false));
+        assert!(!ignored(&stack, "sub/docs/x.md", false));
+        assert!(ignored(&stack, "a.tmp", false));
+        assert!(!ignored(&stack, "keep.tmp", false));
+        assert!(!ignored(&stack, "plain.txt", false));
+        // the .gitignore of a subdirectory, until it is left
+        let depth = stack.push_dir(&root.join("sub"));
+        assert!(!ignored(&stack, "sub/a.tmp", false));
+        assert!(ignored(&stack, "a.tmp", false));
+        stack.pop_to(depth);
+        assert!(ignored(&stack, "sub/a.tmp", false));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+//  JSON printer of the model, compatible with the layout of GNU tree -J,
+//  one object per base directory, then the report of all of them :
+//  [
+//    {"type":"directory","name":".","contents":[
+//      {"type":"file","name":"a.txt","size":12}
+//    ]}
+//  ,
+//    {"type":"report","directories":0,"files":1}
+//  ]
+
+use std::fmt::Write as _;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+
+use crate::treeconfig::PermsFormat;
+use crate::treeconfig::PrintConfig;
+use crate::treeconfig::SizeFormat;
+use crate::treefile::FileTree;
+use crate::treelibs::entry_text;
+use crate::treelibs::my_write;
+use crate::treelibs::stringify_permissions;
+use crate::treelibs::trees_summary;
+use crate::treetime::format_time;
+use crate::treeusers::group_name;
+use crate::treeusers::user_name;
+
+pub(crate) fn print_json(
+    outfile: &mut dyn std::io::Write,
+    trees: &[FileTree],
+    cfg: &PrintConfig,
+    keep_canonical: bool, //  for the base only
+    full_rel_path: bool,  //  for the base only
+) -> io::Result<()> {
+    let mut text = String::from("[\n");
+    for (index, tree) in trees.iter().enumerate() {
+        if index > 0 {
+            text.push_str(",\n");
+        }
+        let mut fields = node_fields(tree, cfg, keep_canonical, full_rel_path);
+        if tree.contents().is_none() {
+            fields += r#","error":"given base is not a directory""#;
+        }
+        write_node(&mut text, tree, fields, cfg, 1);
+    }
+    if !cfg.noreport {
+        let summary = trees_summary(trees, cfg);
+        let size = if cfg.size != SizeFormat::None {
+            format!(",\"size\":{}", summary.bytes)
+        } else {
+            String::new()
+        };
+        let _ = write!(
+            text,
+            "\n,\n  {{\"type\":\"report\"{},\"directories\":{},\"files\":{}}}",
+            size, summary.directories, summary.files
+        );
+    }
+    text.push_str("\n]");
+    my_write(outfile, &text)
+}
+
+// "node" and all its contents, at indentation "level"
+fn write_node(text: &mut String, node: &FileTree, fields: String, cfg: &PrintConfig, level: usize) {
+    let indent = "  ".repeat(level);
+    let _ = write!(text, "{}{{{}", indent, fields);
+    if let FileTree::LinkNode(link) = node {
+        if let Some(reason) = link.not_followed() {
+            let _ = write!(text, r#","error":"{}""#, reason);
+        }
+    }
+    let dir = match node.contents() {
+        Some(dir) => dir,
+        None => {
+            text.push('}');
+            return;
+        }
+    };
+    if let Some(message) = dir.status.message() {
+        let _ = write!(text, r#","error":"{}"}}"#, message);
+        return;
+    }
+    text.push_str(",\"contents\":[");
+    for (index, entry) in dir.entries.iter().enumerate() {
+        text.push_str(if index == 0 { "\n" } else { ",\n" });
+        let fields = node_fields(entry, cfg, cfg.keep_canonical, cfg.full_rel_path);
+        write_node(text, entry, fields, cfg, level + 1);
+    }
+    if let Some(omitted) = &dir.omitted {
+        text.push_str(if dir.entries.is_empty() { "\n" } else { ",\n" });
+        let _ = write!(
+            text,
+            r#"{}  {{"type":"omitted","entries":{},"size":{}}}"#,
+            indent, omitted.entries, omitted.bytes
+        );
+    }
+    if dir.entries.is_empty() && dir.omitted.is_none() {
+        text.push_str("]}");
+    } else {
+        let _ = write!(text, "\n{}]}}", indent);
+    }
+}
+
+// fields of "node" itself, without its contents
+fn node_fields(
+    node: &FileTree,
+    cfg: &PrintConfig,
+    keep_canonical: bool,
+    full_rel_path: bool,
+) -> String {
+    let (filename, symlink) = entry_text(node, keep_canonical, full_rel_path, cfg.names);
+    let node_type = match node {
+        FileTree::DirNode(_) => "directory",
+        FileTree::FileNode(_) => "file",
+        FileTree::LinkNode(_) => "link",
+    };
+    let mut fields = format!(
+        "\"type\":\"{}\",\"name\":\"{}\"",
+        node_type,
+        escape(&filename)
+    );
+    if let FileTree::LinkNode(_) = node {
+        let _ = write!(fields, ",\"target\":\"{}\"", escape(&symlink));
+    }
+    if let Some(mtd) = node.metadata() {
+        let mode = mtd.permissions().mode();
+        match cfg.perms {
+            PermsFormat::Symbolic => {
+                let _ = write!(
+                    fields,
+                    ",\"mode\":\"{:04o}\",\"prot\":\"{}\"",
+                    mode & 0o7777,
+                    stringify_permissions(mode)
+                );
+            }
+            PermsFormat::Numeric => {
+                let _ = write!(fields, ",\"mode\":\"{:04o}\"", mode & 0o7777);
+            }
+            PermsFormat::None => {}
+        }
+        if cfg.user {
+            let user = user_name(mtd.uid(), cfg.numeric_ids);
+            let _ = write!(fields, ",\"user\":\"{}\"", escape(&user));
+        }
+        if cfg.group {
+            let group = group_name(mtd.gid(), cfg.numeric_ids);
+            let _ = write!(fields, ",\"group\":\"{}\"", escape(&group));
+        }
+        if cfg.size != SizeFormat::None {
+            let _ = write!(fields, ",\"size\":{}", node.size(cfg.size_kind));
+        }
+        if cfg.date {
+            let _ = write!(fields, ",\"time\":\"{}\"", escape(&format_time(mtd, cfg)));
+        }
+    }
+    fields
+}
+
+// JSON string escapes
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -3,11 +3,12 @@
 //  extern crate pretty_bytes;
 
 use std::error::Error;
+use std::ffi::OsStr;
 use std::fs;
-use std::fs::File;
 use std::io;
-use std::io::BufWriter;
 use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use std::path::PathBuf;
@@ -16,13 +17,75 @@ use std::path::PathBuf;
 //  use bytesize::ByteSize;
 //  use pretty_bites::converter::convert;
 
-use crate::Opt;
+use crate::treecolors::paint_name;
+use crate::treecolors::paint_target;
+use crate::treeconfig::Charset;
+use crate::treeconfig::NameStyle;
+use crate::treeconfig::OutputFormat;
+use crate::treeconfig::PermsFormat;
+use crate::treeconfig::PrintConfig;
+use crate::treeconfig::SizeFormat;
+use crate::treeconfig::SizeKind;
+use crate::treeconfig::TreeConfig;
+use crate::treefile::base;
+use crate::treefile::replay;
+use crate::treefile::stream;
+use crate::treefile::walk;
+use crate::treefile::DirStatus;
+use crate::treefile::Directory;
+use crate::treefile::File;
+use crate::treefile::FileTree;
+use crate::treefile::Omitted;
+use crate::treefile::Summary;
+use crate::treefile::Visitor;
+use crate::treehtml::print_html;
+use crate::treejson::print_json;
+use crate::treetime::format_time;
+use crate::treeusers::group_name;
+use crate::treeusers::user_name;
+use crate::treexml::print_xml;
 
-const OTHER_CHILD: &str = "│   "; // prefix: pipe
-const OTHER_ENTRY: &str = "├── "; // connector: tee
-const FINAL_CHILD: &str = "    "; // prefix: no siblings
-const FINAL_ENTRY: &str = "└── "; // connector: elbow
-const NO_INDENT: &str = "";
+pub(crate) const NO_INDENT: &str = "";
+
+/// Told of each error met on a single entry or base : its path, and the error
+pub type Warn<'a> = &'a mut dyn FnMut(&Path, &io::Error);
+
+/// connectors and prefixes drawing the lines of the tree, as configured by --charset
+pub(crate) struct Lines {
+    /// prefix: pipe
+    pub(crate) other_child: String,
+    /// connector: tee
+    pub(crate) other_entry: String,
+    /// prefix: no siblings
+    pub(crate) final_child: String,
+    /// connector: elbow
+    pub(crate) final_entry: String,
+}
+
+impl Lines {
+    // "indent" columns per level, 4 if 0
+    pub(crate) fn new(charset: Charset, indent: usize) -> Self {
+        // VT100 : "x", "t", "m" and "q" are lines in the graphics mode, entered by ESC ( 0
+        let (chars, enter, leave) = match charset {
+            Charset::Utf8 => (['│', '├', '└', '─'], "", ""),
+            Charset::Ascii => (['|', '|', '`', '-'], "", ""),
+            Charset::Vt100 => (['x', 't', 'm', 'q'], "\u{1B}(0", "\u{1B}(B"),
+            Charset::Custom(chars) => (chars, "", ""),
+        };
+        let [vertical, tee, elbow, horizontal] = chars;
+        let width = match indent {
+            0 => 4,
+            width => width.max(2),
+        };
+        let horizontals = horizontal.to_string().repeat(width - 2);
+        Lines {
+            other_child: format!("{}{}{}{}", enter, vertical, leave, " ".repeat(width - 1)),
+            other_entry: format!("{}{}{}{} ", enter, tee, horizontals, leave),
+            final_child: " ".repeat(width),
+            final_entry: format!("{}{}{}{} ", enter, elbow, horizontals, leave),
+        }
+    }
+}
 
 //  const PETA: u64 = 1_125_899_906_842_624;
 //  const TERA: u64 = 1_099_511_627_776;
@@ -30,50 +93,20 @@ const NO_INDENT: &str = "";
 //  const MEGA: u64 = 1_048_576;
 //  const KILO: u64 = 1_024;
 
-#[allow(dead_code)]
-pub enum ANSIColor {
-    Black,
-    Red,
-    Green,
-    Yellow,
-    Blue,
-    Magenta,
-    Cyan,
-    White,
-    Reset,
-}
-
 //  ------------------------- constants for permissions ------------------------- */
 // from : https://man7.org/linux/man-pages/man7/inode.7.html
 const S_IFMT: u32 = 0o0_170_000; //  general mask
+const S_IFSOCK: u32 = 0o0_140_000; //  socket
 const S_IFLNK: u32 = 0o0_120_000; //  symbolic link
 const S_IFREG: u32 = 0o0_100_000; //  regular file
+const S_IFBLK: u32 = 0o0_060_000; //  block device
 const S_IFDIR: u32 = 0o0_040_000; //  directory
-                                  //  const S_IFSOCK :u32 =   0o0140000;  //  socket
-                                  //  const S_IFBLK  :u32 =   0o0060000;  //  block device
-                                  //  const S_IFCHR  :u32 =   0o0020000;  //  character device
-                                  //  const S_IFIFO  :u32 =   0o0010000;  //  FIFO
+const S_IFCHR: u32 = 0o0_020_000; //  character device
+const S_IFIFO: u32 = 0o0_010_000; //  FIFO
+const S_ISUID: u32 = 0o0_004_000; //  set-user-ID
+const S_ISGID: u32 = 0o0_002_000; //  set-group-ID
+const S_ISVTX: u32 = 0o0_001_000; //  sticky
                                   //  ------------------------- constants for permissions ------------------------- */
-                                  //  Checks for matches where all arms match a reference,
-                                  //  suggesting to remove the reference and deref the matched expression instead.
-                                  //  It also checks for if let &foo = bar blocks.
-#[allow(dead_code)]
-impl ANSIColor {
-    pub fn as_string(&self) -> &str {
-        match *self {
-            ANSIColor::Black => "\u{001B}[0;30m",
-            ANSIColor::Red => "\u{001B}[0;31m",
-            ANSIColor::Green => "\u{001B}[0;32m",
-            ANSIColor::Yellow => "\u{001B}[0;33m",
-            ANSIColor::Blue => "\u{001B}[0;34m",
-            ANSIColor::Magenta => "\u{001B}[0;35m",
-            ANSIColor::Cyan => "\u{001B}[0;36m",
-            ANSIColor::White => "\u{001B}[0;37m",
-            ANSIColor::Reset => "\u{001B}[0;0m",
-        }
-    }
-}
-
 pub fn convert(num: u64, delimiter: u64) -> String {
     let units = ["B", "K", "M", "G", "T", "P", "E", "Z", "Y"];
     let f_delimiter = delimiter as f64;
@@ -97,7 +130,8 @@ pub fn convert(num: u64, delimiter: u64) -> String {
     }
 }
 
-fn stringify_permissions(perms: u32) -> String {
+// type and permissions as per "ls -l" : "drwxr-sr-x", "crw-rw-rw-", "-rwsr-xr-x"...
+pub(crate) fn stringify_permissions(perms: u32) -> String {
     let mut vec_perms: Vec<char> = "rwxrwxrwx".chars().collect();
     let mut b = 1;
     let mut i = 0;
@@ -108,466 +142,703 @@ fn stringify_permissions(perms: u32) -> String {
         b <<= 1;
         i += 1;
     }
+    // special bits take the place of the execute bit : lowercase if it is set too
+    for (bit, index, letter) in [(S_ISUID, 2, 's'), (S_ISGID, 5, 's'), (S_ISVTX, 8, 't')] {
+        if perms & bit != 0 {
+            vec_perms[index] = if vec_perms[index] == 'x' {
+                letter
+            } else {
+                letter.to_ascii_uppercase()
+            };
+        }
+    }
     let str_perms: String = vec_perms.into_iter().collect();
     let pre_string: String = match perms & S_IFMT {
+        S_IFSOCK => "s".to_string(),
         S_IFLNK => "l".to_string(),
-        S_IFDIR => "d".to_string(),
         S_IFREG => "-".to_string(),
+        S_IFBLK => "b".to_string(),
+        S_IFDIR => "d".to_string(),
+        S_IFCHR => "c".to_string(),
+        S_IFIFO => "p".to_string(),
         _ => "?".to_string(),
     };
     pre_string + &str_perms
 }
 
-fn visit_dirs(
-    outfile: &mut dyn std::io::Write,
-    dirs_visited: &mut Vec<PathBuf>,
-    dir: &Path,
-    prefix: &str,
-    depth: usize,
-    opt: &Opt,
-) -> io::Result<()> {
-    //  my_write(outfile, "visitDir");
-    // opt.level == 0 -> go all the way
-    // opt.level != 0 -> go only to depth==opt.level
-    if (opt.level != 0) & (depth == opt.level) {
-        return Ok(());
-    }
-    if dir.is_dir() {
-        // get elements in this directory
-        let entry_set = fs::read_dir(dir)?; // contains DirEntry
-        let mut entries = entry_set
-            .filter_map(|v| match v.ok() {
-                Some(v) => {
-                    if opt.show_hidden {
-                        Some(v)
-                    } else if v.file_name().to_str()?.starts_with('.') {
-                        None
-                    } else {
-                        Some(v)
-                    }
-                }
-                None => None,
-            })
-            .collect::<Vec<_>>();
-        entries.sort_by(|a, b| a.path().file_name().cmp(&b.path().file_name()));
-        let num_entries: usize = entries.len();
-        // if current dir has too many entries, print none
-        //  println!("testing filelimit");
-        if (opt.filelimit != 0) && num_entries > opt.filelimit {
-            my_write(
-                outfile,
-                &format!(
-                    "{}{}[{} entries exceeded filelimit, not printing dir]",
-                    prefix, FINAL_ENTRY, num_entries
-                ),
-            );
-            return Ok(());
-        }
-        if opt.only_dir {
-            entries.retain(|x| x.path().is_dir());
+// bracketed info column : permissions and size, as selected in cfg
+pub(crate) fn info_column(node: &FileTree, cfg: &PrintConfig) -> String {
+    if cfg.perms == PermsFormat::None
+        && cfg.size == SizeFormat::None
+        && !cfg.user
+        && !cfg.group
+        && !cfg.date
+    {
+        return String::new();
+    }
+    let mtd = match node.metadata() {
+        Some(mtd) => mtd,
+        None => return "[?] ".to_string(),
+    };
+    let u32perms = mtd.permissions().mode();
+    //  let realsize = this_path.size_on_disk_fast(&mtd).unwrap();
+    //  let realsize = this_path.size_on_disk().unwrap();
+    let realsize = node.size(cfg.size_kind);
+    let date = match (cfg.date, cfg.size) {
+        (false, _) => String::new(),
+        (true, SizeFormat::None) => format_time(mtd, cfg),
+        (true, _) => format!(" {}", format_time(mtd, cfg)),
+    };
+    format!(
+        "[{}{}{}{}{}] ",
+        match cfg.perms {
+            PermsFormat::Numeric => format!("{:04o} ", u32perms & 0o7777),
+            PermsFormat::Symbolic => stringify_permissions(u32perms) + " ",
+            PermsFormat::None => String::new(),
+        },
+        match cfg.user {
+            true => format!("{:<8} ", user_name(mtd.uid(), cfg.numeric_ids)),
+            false => String::new(),
+        },
+        match cfg.group {
+            true => format!("{:<8} ", group_name(mtd.gid(), cfg.numeric_ids)),
+            false => String::new(),
+        },
+        match cfg.size {
+            SizeFormat::Bytes => format!("{:5}", realsize),
+            SizeFormat::HumanSi => format!("{:>6} iB", convert(realsize, 1000_u64)),
+            SizeFormat::HumanIec => format!("{:>6}", convert(realsize, 1024_u64)),
+            SizeFormat::None => String::new(),
+        },
+        date
+    )
+}
+
+// prints the entries of a tree as indented lines, as they are visited
+struct TextPrinter<'a> {
+    outfile: &'a mut dyn Write,
+    cfg: &'a PrintConfig,
+    prefix: String,
+    /// lengths of "prefix" before each enter()
+    depths: Vec<usize>,
+    lines: Lines,
+    /// prefix of the contents of the last entry printed
+    child: String,
+    summary: Summary,
+    /// told of errors as soon as they are met, when the tree is printed as it is walked
+    warn: Option<Warn<'a>>,
+}
+
+impl<'a> TextPrinter<'a> {
+    fn new(outfile: &'a mut dyn Write, cfg: &'a PrintConfig, warn: Option<Warn<'a>>) -> Self {
+        TextPrinter {
+            outfile,
+            cfg,
+            prefix: String::new(),
+            depths: Vec::new(),
+            lines: Lines::new(cfg.charset, cfg.indent),
+            child: String::new(),
+            summary: Summary::default(),
+            warn,
         }
-        // help avoid symlink cycles by pre-listing directories which will certainly be visited
-        if !opt.fast_rsc {
-            for iter_entry in &entries {
-                if iter_entry.path().is_dir() {
-                    dirs_visited.push(fs::canonicalize(iter_entry.path()).unwrap());
-                }
+    }
+}
+
+impl Visitor for TextPrinter<'_> {
+    fn status(&mut self, directory: &Directory) -> io::Result<()> {
+        if let DirStatus::Error(err) = &directory.status {
+            self.summary.errors += 1;
+            if let Some(warn) = self.warn.as_mut() {
+                warn(&directory.path, err);
             }
         }
-        for (index, entry) in entries.iter().enumerate() {
-            let path = entry.path();
-            let entry_to_use;
-            let child_to_use;
-            if opt.no_indent {
-                entry_to_use = NO_INDENT;
-                child_to_use = NO_INDENT;
-            } else {
-                entry_to_use = if index == entries.len() - 1 {
-                    FINAL_ENTRY
-                } else {
-                    OTHER_ENTRY
-                };
-                child_to_use = if index == entries.len() - 1 {
-                    FINAL_CHILD
-                } else {
-                    OTHER_CHILD
-                };
-            }
-            if !opt.only_dir || path.is_dir() {
-                // do all OR ( do only dirs AND is dir )
-                if opt.perms || opt.num_perms || opt.size || opt.hsize || opt.hsize_ib {
-                    let this_path = Path::new(&path);
-                    let mtd = fs::symlink_metadata(this_path)?;
-                    let u32perms = mtd.permissions().mode();
-                    //  let realsize = this_path.size_on_disk_fast(&mtd).unwrap();
-                    //  let realsize = this_path.size_on_disk().unwrap();
-                    let realsize = mtd.len();
-                    // here perms is a number
-                    let tot_perm_str = stringify_permissions(u32perms);
-                    let internal = format!(
-                        "[{}{}] ",
-                        if opt.num_perms {
-                            format!("{:o }", u32perms)
-                        } else if opt.perms {
-                            tot_perm_str + " "
-                        } else {
-                            "".to_string()
-                        },
-                        if opt.size {
-                            format!("{:5}", realsize)
-                        } else if opt.hsize || opt.hsize_ib {
-                            if opt.hsize_ib {
-                                format!("{:>6} iB", convert(realsize, 1000_u64))
-                            } else {
-                                format!("{:>6}", convert(realsize, 1024_u64))
-                            }
-                        } else {
-                            String::new()
-                        }
-                    );
-                    my_write(
-                        outfile,
-                        &format!(
-                            "{}{}{}{}",
-                            prefix,
-                            entry_to_use,
-                            internal,
-                            color_output(opt.colorize, &path, opt.keep_canonical, opt.full_rel_path)
-                        ),
-                    );
-                } else {
-                    my_write(
-                        outfile,
-                        &format!(
-                            "{}{}{}",
-                            prefix,
-                            entry_to_use,
-                            color_output(opt.colorize, &path, opt.keep_canonical, opt.full_rel_path)
-                        ),
-                    );
-                }
+        // if current dir has too many entries, or cannot be read, print none
+        match directory.status.message() {
+            Some(message) => my_write(
+                self.outfile,
+                format!("{}{}[{}]", self.prefix, self.lines.final_entry, message),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    fn entry(&mut self, entry: &FileTree, last: bool) -> io::Result<()> {
+        let cfg = self.cfg;
+        let entry_to_use;
+        if cfg.no_indent {
+            entry_to_use = NO_INDENT;
+            self.child.clear();
+        } else if last {
+            entry_to_use = self.lines.final_entry.as_str();
+            self.child.clone_from(&self.lines.final_child);
+        } else {
+            entry_to_use = self.lines.other_entry.as_str();
+            self.child.clone_from(&self.lines.other_child);
+        }
+        let mut line =
+            format!("{}{}{}", self.prefix, entry_to_use, info_column(entry, cfg)).into_bytes();
+        line.extend(color_output(
+            cfg.colorize,
+            cfg.names,
+            entry,
+            cfg.keep_canonical,
+            cfg.full_rel_path,
+        ));
+        my_write(self.outfile, line)?;
+        self.summary.add(Summary::of_entry(entry));
+        if let FileTree::FileNode(File {
+            path,
+            metadata: Err(err),
+        }) = entry
+        {
+            if let Some(warn) = self.warn.as_mut() {
+                warn(path, err);
             }
-            if path.is_dir() {
-                // enter path and tree() it
-                let this_metadata = fs::symlink_metadata(&path)?;
-                let this_is_symlink = this_metadata.file_type().is_symlink();
-                if this_is_symlink {
-                    //  my_write(outfile, format!("ad ora il visited-dir ha : {:#?}", dirs_visited));
-                    //  my_write(outfile, format!("ciao, il symlink punta qua : {}", fs::canonicalize(path.clone()).unwrap().display()) );
-                    // should we follow symlink
-                    if !opt.follow_symlink {
-                        continue;
-                    }
-                    // avoid symlink cycles
-                    if !opt.fast_rsc
-                        && dirs_visited.contains(&fs::canonicalize(path.clone()).unwrap())
-                    {
-                        my_write(
-                            outfile,
-                            &format!(
-                                "{}{}[symlink cycle detected, will not expand it]",
-                                prefix.to_string() + child_to_use,
-                                FINAL_ENTRY
-                            ),
-                        );
-                        continue;
-                    }
-                }
-                let depth_new = depth + 1;
-                //  let prefix_new = prefix.clone() + "    ";
-                let prefix_new = prefix.to_string() + child_to_use;
-                visit_dirs(outfile, dirs_visited, &path, &prefix_new, depth_new, opt)?;
+        }
+        if let FileTree::LinkNode(link) = entry {
+            if let Some(reason) = link.not_followed() {
+                my_write(
+                    self.outfile,
+                    format!(
+                        "{}{}{}[{}]",
+                        self.prefix, self.child, self.lines.final_entry, reason
+                    ),
+                )?;
             }
         }
+        Ok(())
     }
-    Ok(())
-}
 
-// visit base directory
-fn visit_base(
-    outfile: &mut dyn std::io::Write,
-    base: &Path,          // done
-    prefix: &str,         // done
-    keep_canonical: bool, // done         //  --full_rel_path
-    full_rel_path: bool,      // done         //  -f
-    opt: &Opt,
-) -> io::Result<()> {
-    //  my_write(outfile, "visitBase");
-    if opt.perms || opt.num_perms || opt.size || opt.hsize || opt.hsize_ib {
-        let this_path = Path::new(&base);
-        let mtd = fs::symlink_metadata(this_path)?;
-        let u32perms = mtd.permissions().mode();
-        let realsize = mtd.len();
-        let tot_perm_str = stringify_permissions(u32perms);
-        let internal = format!(
-            "[{}{}] ",
-            if opt.num_perms {
-                format!("{:o }", u32perms)
-            } else if opt.perms {
-                tot_perm_str + " "
-            } else {
-                "".to_string()
-            },
-            if opt.size {
-                format!("{:5}", realsize)
-            } else if opt.hsize || opt.hsize_ib {
-                if opt.hsize_ib {
-                    format!("{:>6} iB", convert(realsize, 1000_u64))
-                } else {
-                    format!("{:>6}", convert(realsize, 1024_u64))
-                }
-            } else {
-                String::new()
-            }
-        );
+    fn enter(&mut self) {
+        self.depths.push(self.prefix.len());
+        self.prefix.push_str(&self.child);
+    }
+
+    fn leave(&mut self) {
+        if let Some(depth) = self.depths.pop() {
+            self.prefix.truncate(depth);
+        }
+    }
+
+    fn omitted(&mut self, omitted: &Omitted) -> io::Result<()> {
+        let entry_to_use = if self.cfg.no_indent {
+            NO_INDENT
+        } else {
+            self.lines.final_entry.as_str()
+        };
         my_write(
-            outfile,
-            &format!(
+            self.outfile,
+            format!(
                 "{}{}{}",
-                prefix,
-                internal,
-                color_output(opt.colorize, base, keep_canonical, full_rel_path)
+                self.prefix,
+                entry_to_use,
+                omitted_line(omitted, self.cfg)
             ),
-        );
-    } else {
-        my_write(
-            outfile,
-            &format!(
-                "{}{}",
-                prefix,
-                color_output(opt.colorize, base, keep_canonical, full_rel_path)
-            ),
-        );
+        )
     }
+}
 
-    Ok(())
+// visit base directory
+fn visit_base(
+    outfile: &mut dyn std::io::Write,
+    base: &FileTree,
+    prefix: &str,
+    keep_canonical: bool, //  --keep_canonical
+    full_rel_path: bool,  //  -f
+    cfg: &PrintConfig,
+) -> io::Result<()> {
+    let mut line = format!("{}{}", prefix, info_column(base, cfg)).into_bytes();
+    line.extend(color_output(
+        cfg.colorize,
+        cfg.names,
+        base,
+        keep_canonical,
+        full_rel_path,
+    ));
+    my_write(outfile, line)
 }
 
-fn is_executable(path: &Path) -> bool {
-    let metadata = match fs::symlink_metadata(path) {
-        Ok(value) => value,
-        Err(_err) => return false,
-    };
-    metadata.permissions().mode() & 0o111 != 0
+pub(crate) fn is_executable(node: &FileTree) -> bool {
+    match node.metadata() {
+        Some(metadata) => metadata.permissions().mode() & 0o111 != 0,
+        None => false,
+    }
 }
 
-fn color_output(
-    colorize: bool,
-    path: &Path,
+// name to print for "node", and the target to print after it if it is a symlink
+// these are bytes : with -N, those which are not UTF-8 are printed as they are
+pub(crate) fn entry_name(
+    node: &FileTree,
     keep_canonical: bool,
     full_rel_path: bool,
-) -> std::string::String {
-    //  println!("path : {} ;", path.display());
-    let filename: String;
-    let symlink: String;
-    let parent = path.parent().unwrap();
-    //  println!("path : {} ; parent is : {} ", path.display(), parent.display());
-    let mut is_sym_and_target_exists = false;
-    //  .to_string_lossy().into_owned() == .to_str().unwrap().to_owned(),
-    //  ma funziona anche se il path non è UTF8 valido
-    //  println!("{}", path.display());
+    style: NameStyle,
+) -> (Vec<u8>, Vec<u8>) {
+    let path = node.path();
+    let filename: Vec<u8>;
+    let symlink: Vec<u8>;
+    let (link_target, is_sym_and_target_exists) = match node {
+        FileTree::LinkNode(link) => (link.target.as_deref(), link.target_metadata.is_some()),
+        _ => (None, false),
+    };
+    let target_name = || match link_target {
+        Some(v) => render_name(v.as_os_str(), style),
+        None => Vec::new(),
+    };
     if !keep_canonical && !full_rel_path {
         // default path
-        //  println!("enter first if : {} ; parent is : {} ", path.display(), parent.display());
-        if path == Path::new(".") || path == Path::new("..") {
-            filename = path.to_string_lossy().into_owned();
-        } else {
-            filename = path.file_name().unwrap().to_string_lossy().into_owned();
-        }
-        symlink = match fs::read_link(path) {
-            Ok(v) => v.to_string_lossy().into_owned(),
-            Err(_err) => String::new(),
+        // ".", ".." and "/" have no file name
+        filename = match path.file_name() {
+            Some(name) if path != Path::new(".") && path != Path::new("..") => {
+                render_name(name, style)
+            }
+            _ => render_name(path.as_os_str(), style),
         };
+        symlink = target_name();
     } else if full_rel_path {
-        //  println!("enter second if : {} ; parent is : {} ", path.display(), parent.display());
-        filename = path.to_string_lossy().into_owned();
-        symlink = match fs::read_link(path) {
-            Ok(v) => v.to_string_lossy().into_owned(),
-            Err(_err) => String::new(),
-        };
+        filename = render_name(path.as_os_str(), style);
+        symlink = target_name();
     } else {
         // full canonical path
-        //  println!("enter last if : {} ; parent is : {} ", path.display(), parent.display());
-        let _can_path = fs::canonicalize(path).unwrap();
-        let _can_parent = fs::canonicalize(_can_path.parent().unwrap()).unwrap();
-        //  println!("enter last if : canpath {} ; canparent is : {} ", _can_path.display(), _can_parent.display());
-        //  if parent.exists(){
-        //      filename = fs::canonicalize(parent)
-        //          .unwrap()
-        //          .join(path.file_name().unwrap())
-        //          .to_string_lossy()
-        //          .into_owned();
-        //  }else{
-        //      filename = "".to_string();
-        //  }
-        filename = format!("{}", _can_path.display());
-        if path.is_symlink() {
-            if parent.join(path.read_link().unwrap()).exists() {
-                symlink = fs::canonicalize(path)
-                    .unwrap()
-                    .to_string_lossy()
-                    .into_owned();
-            } else {
-                symlink = path.read_link().unwrap().to_string_lossy().into_owned();
+        let can_path = canonical_path(path);
+        filename = render_name(can_path.as_os_str(), style);
+        symlink = match link_target {
+            Some(_) if is_sym_and_target_exists => filename.clone(),
+            _ => target_name(),
+        };
+    }
+    (filename, symlink)
+}
+
+// entry_name() as text, for JSON, XML and HTML documents, which must be UTF-8 :
+// there, -N prints the bytes which are not UTF-8 as U+FFFD
+pub(crate) fn entry_text(
+    node: &FileTree,
+    keep_canonical: bool,
+    full_rel_path: bool,
+    style: NameStyle,
+) -> (String, String) {
+    let (filename, symlink) = entry_name(node, keep_canonical, full_rel_path, style);
+    (
+        String::from_utf8_lossy(&filename).into_owned(),
+        String::from_utf8_lossy(&symlink).into_owned(),
+    )
+}
+
+// "name" made safe to print as chosen by "style" : a name cannot move the cursor,
+// change colors, start a new line or reverse the text which follows it, unless -N
+pub(crate) fn render_name(name: &OsStr, style: NameStyle) -> Vec<u8> {
+    if style == NameStyle::Raw {
+        return name.as_bytes().to_vec();
+    }
+    let mut text = Vec::new();
+    let escape = |text: &mut Vec<u8>, bytes: &[u8]| {
+        for byte in bytes {
+            let _ = write!(text, "\\x{:02x}", byte);
+        }
+    };
+    if style == NameStyle::Quoted {
+        text.push(b'"');
+    }
+    for chunk in name.as_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            let mut utf8 = [0; 4];
+            let bytes = c.encode_utf8(&mut utf8).as_bytes();
+            match style {
+                _ if !unprintable(c) => {
+                    if style == NameStyle::Quoted && (c == '"' || c == '\\') {
+                        text.push(b'\\');
+                    }
+                    text.extend_from_slice(bytes);
+                }
+                NameStyle::Question => text.push(b'?'),
+                _ => escape(&mut text, bytes),
             }
-        } else {
-            symlink = String::new();
+        }
+        match style {
+            NameStyle::Question => text.extend(chunk.invalid().iter().map(|_| b'?')),
+            _ => escape(&mut text, chunk.invalid()),
         }
     }
-    //  println!("{}", filename);
-    if !symlink.is_empty() {
-        is_sym_and_target_exists = parent.join(path.read_link().unwrap()).exists();
-    }
-
-    if colorize {
-        if path.is_dir() {
-            if symlink.is_empty() {
-                format!(
-                    "{}{}{}",
-                    ANSIColor::Yellow.as_string(),
-                    filename,
-                    ANSIColor::Reset.as_string()
-                )
-            } else if is_sym_and_target_exists {
-                format!(
-                    "{}{}{} -> {}{}{}",
-                    ANSIColor::Cyan.as_string(),
-                    filename,
-                    ANSIColor::Reset.as_string(),
-                    ANSIColor::Yellow.as_string(),
-                    symlink,
-                    ANSIColor::Reset.as_string()
-                )
+    if style == NameStyle::Quoted {
+        text.push(b'"');
+    }
+    text
+}
+
+// control characters, and the marks and overrides of bidirectional text
+fn unprintable(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}'
+    ) || c.is_control()
+}
+
+// full canonical path of "path" ; dangling symlinks get their canonical parent,
+// paths which cannot be canonicalized at all (e.g. removed meanwhile) are kept as they are
+fn canonical_path(path: &Path) -> PathBuf {
+    if let Ok(can_path) = fs::canonicalize(path) {
+        return can_path;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
             } else {
-                format!(
-                    "{}{}{} -> {}",
-                    ANSIColor::Red.as_string(),
-                    filename,
-                    ANSIColor::Reset.as_string(),
-                    symlink,
-                )
+                parent
+            };
+            match fs::canonicalize(parent) {
+                Ok(can_parent) => can_parent.join(name),
+                Err(_) => path.to_path_buf(),
             }
-        } else if is_executable(path) {
-            if symlink.is_empty() {
-                format!(
-                    "{}{}{}",
-                    ANSIColor::Green.as_string(),
-                    filename,
-                    ANSIColor::Reset.as_string()
-                )
-            } else if is_sym_and_target_exists {
-                format!(
-                    "{}{}{} -> {}{}{}",
-                    ANSIColor::Cyan.as_string(),
-                    filename,
-                    ANSIColor::Reset.as_string(),
-                    ANSIColor::Green.as_string(),
-                    symlink,
-                    ANSIColor::Reset.as_string()
-                )
-            } else {
-                format!(
-                    "{}{}{} -> {}",
-                    ANSIColor::Red.as_string(),
-                    filename,
-                    ANSIColor::Reset.as_string(),
-                    symlink,
-                )
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+fn color_output(
+    colorize: bool,
+    names: NameStyle,
+    node: &FileTree,
+    keep_canonical: bool,
+    full_rel_path: bool,
+) -> Vec<u8> {
+    let (filename, symlink) = entry_name(node, keep_canonical, full_rel_path, names);
+    if !colorize {
+        return filename;
+    }
+    let mut name = paint_name(node, &filename);
+    if !symlink.is_empty() {
+        name.extend_from_slice(b" -> ");
+        name.extend(paint_target(node, &symlink));
+    }
+    name
+}
+
+// totals of "trees" together, with --du the bytes are those of the whole subtrees of the bases
+pub(crate) fn trees_summary(trees: &[FileTree], cfg: &PrintConfig) -> Summary {
+    let mut total = Summary::default();
+    for tree in trees {
+        let mut summary = tree.contents().map(|dir| dir.summary()).unwrap_or_default();
+        if let Some(du) = tree.contents().and_then(|dir| dir.du) {
+            match cfg.size_kind {
+                SizeKind::Apparent => summary.bytes = du.apparent,
+                SizeKind::Allocated => summary.bytes = du.allocated,
+                SizeKind::Length => {}
             }
-        } else {
-            format!(
-                "{}{}{}",
-                ANSIColor::Magenta.as_string(),
-                filename,
-                ANSIColor::Reset.as_string()
-            )
         }
-    } else {
-        filename.to_string()
+        total.add(summary);
     }
+    total
+}
+
+// a total of "bytes", as the report prints it : human readable if no size format is chosen
+pub(crate) fn total_size(bytes: u64, cfg: &PrintConfig) -> String {
+    match cfg.size {
+        SizeFormat::Bytes => format!("{} bytes", bytes),
+        SizeFormat::HumanSi => format!("{} iB", convert(bytes, 1000_u64)),
+        SizeFormat::HumanIec | SizeFormat::None => convert(bytes, 1024_u64),
+    }
+}
+
+// "[+ N more entries, size]" line of the entries left out by --top
+pub(crate) fn omitted_line(omitted: &Omitted, cfg: &PrintConfig) -> String {
+    format!(
+        "[+ {} more {}, {}]",
+        omitted.entries,
+        if omitted.entries == 1 {
+            "entry"
+        } else {
+            "entries"
+        },
+        total_size(omitted.bytes, cfg)
+    )
+}
+
+// final report : "[size used in ]N directories, M files[ (L symlinks)][, E errors]"
+pub(crate) fn report_line(summary: &Summary, cfg: &PrintConfig) -> String {
+    let plural = |count: usize, one: &str, many: &str| {
+        format!("{} {}", count, if count == 1 { one } else { many })
+    };
+    let used = match cfg.size {
+        SizeFormat::None => String::new(),
+        _ => format!("{} used in ", total_size(summary.bytes, cfg)),
+    };
+    let links = if summary.links > 0 {
+        format!(" ({})", plural(summary.links, "symlink", "symlinks"))
+    } else {
+        String::new()
+    };
+    let errors = if summary.errors > 0 {
+        format!(", {}", plural(summary.errors, "error", "errors"))
+    } else {
+        String::new()
+    };
+    format!(
+        "{}{}, {}{}{}",
+        used,
+        plural(summary.directories, "directory", "directories"),
+        plural(summary.files, "file", "files"),
+        links,
+        errors
+    )
 }
 
-fn my_write(writer: &mut dyn std::io::Write, text: &str) {
-    writeln!(writer, "{}", text).unwrap();
+// "text" and a new line, its bytes written as they are
+pub(crate) fn my_write(writer: &mut dyn std::io::Write, text: impl AsRef<[u8]>) -> io::Result<()> {
+    writer.write_all(text.as_ref())?;
+    writer.write_all(b"\n")
+}
+
+// the whole trees, one after the other, as indented lines
+fn print_text(
+    outfile: &mut dyn std::io::Write,
+    trees: &[FileTree],
+    cfg: &PrintConfig,
+    keep_canonical: bool, //  for the base only
+    full_rel_path: bool,  //  for the base only
+) -> io::Result<()> {
+    for tree in trees {
+        visit_base(
+            outfile,
+            tree,
+            "", //  &String::from("")
+            keep_canonical,
+            full_rel_path,
+            cfg,
+        )?;
+        let mut printer = TextPrinter::new(outfile, cfg, None);
+        match tree.contents() {
+            Some(dir) => replay(dir, &mut printer)?,
+            None => not_a_directory(printer.outfile, &printer.lines)?,
+        }
+    }
+    let summary = trees_summary(trees, cfg);
+    if !cfg.noreport {
+        my_write(outfile, format!("\n{}", report_line(&summary, cfg)))?;
+    }
+    Ok(())
 }
 
-//  function "run", gets all input flags and target dir, does search-and-print
-//  opt.level 0 goes to depth-infinity
-//  filelimit 0 means no bound on files in dir
-pub fn run(opt: &Opt) -> Result<(), Box<dyn Error>> {
-    //  //  solution with heap allocations :
-    //  let mut outfile: Box<dyn std::io::Write> = if opt.target_file.is_empty() {
-    //      let stdout = std::io::stdout();
-    //      let stdout = stdout.lock();
-    //      Box::new(stdout)
-    //  } else {
-    //      let file = File::create("my_file.txt").unwrap();
-    //      let buf_file = std::io::BufWriter::new(file);
-    //      Box::new(buf_file)
-    //  };
-    //  //  ---------------------------------------------
-    //  //  solution without heap allocations :
-    let outfile: &mut dyn Write;
-    let mut lockstdout;
-    let mut buf_file;
-
-    if opt.target_file.is_empty() {
-        let stdout = std::io::stdout();
-        lockstdout = stdout.lock();
-        outfile = &mut lockstdout;
+// the whole tree, as indented lines printed while it is walked, for SortMode::None
+// the report is left to the caller, once every base is printed
+fn print_stream(
+    outfile: &mut dyn std::io::Write,
+    directory: &Path,
+    tree_cfg: &TreeConfig,
+    cfg: &PrintConfig,
+    keep_canonical: bool, //  for the base only
+    full_rel_path: bool,  //  for the base only
+    warn: Warn,
+) -> io::Result<Summary> {
+    let mut tree = base(directory)?;
+    visit_base(outfile, &tree, "", keep_canonical, full_rel_path, cfg)?;
+    let mut printer = TextPrinter::new(outfile, cfg, Some(warn));
+    if tree.is_dir() {
+        stream(&mut tree, tree_cfg, &mut printer)?;
     } else {
-        let file = File::create(&opt.target_file)?;
-        buf_file = BufWriter::new(file);
-        outfile = &mut buf_file;
+        not_a_directory(printer.outfile, &printer.lines)?;
     }
-    my_write(outfile, format!("{:?}", opt).as_str());
+    Ok(printer.summary)
+}
+
+fn not_a_directory(outfile: &mut dyn std::io::Write, lines: &Lines) -> io::Result<()> {
+    my_write(
+        outfile,
+        format!(
+            "{}{}[given base is not a directory]",
+            lines.final_child, lines.final_entry
+        ),
+    )
+}
 
+//  function "run", walks the trees rooted in "directories" and prints them to "outfile",
+//  one after the other, followed by a single report of all of them
+//  tree_cfg.level 0 goes to depth-infinity
+//  tree_cfg.filelimit 0 means no bound on files in dir
+//  errors met on single entries are handed to "warn", and counted in the returned summary,
+//  so are the bases which cannot be read, unless none of them can.
+//  "warn" is told of them before the trees are printed, or while they are with -U
+pub fn run(
+    outfile: &mut dyn Write,
+    directories: &[PathBuf],
+    tree_cfg: &TreeConfig,
+    print_cfg: &PrintConfig,
+    warn: Warn,
+) -> Result<Summary, Box<dyn Error>> {
     // force_base_canonical is a flavour implementation of tree of mine.
-    //  let force_base_canonical = false;
-    let mut resulting_canonical = opt.keep_canonical;
-    let mut resulting_full_rel_path = opt.full_rel_path;
-    if opt.base_canonical {
+    let mut resulting_canonical = print_cfg.keep_canonical;
+    let mut resulting_full_rel_path = print_cfg.full_rel_path;
+    if print_cfg.base_canonical {
         resulting_canonical = true;
         resulting_full_rel_path = false;
     }
-    visit_base(
+    // several bases are told apart by their paths as given, "m1/a" and "m2/a" rather than "a"
+    if directories.len() > 1 && !resulting_canonical {
+        resulting_full_rel_path = true;
+    }
+    let mut bases = Vec::new();
+    let mut missing = Vec::new();
+    for directory in directories {
+        match fs::symlink_metadata(directory) {
+            Ok(_) => bases.push(directory.as_path()),
+            Err(err) => missing.push((directory, err)),
+        }
+    }
+    if bases.is_empty() && !missing.is_empty() {
+        let errors: Vec<_> = missing
+            .iter()
+            .map(|(directory, err)| format!("{}: {}", directory.display(), err))
+            .collect();
+        return Err(errors.join("\n").into());
+    }
+    for (directory, err) in &missing {
+        warn(directory, err);
+    }
+    let mut summary = Summary {
+        errors: missing.len(),
+        ..Summary::default()
+    };
+    // unsorted text needs no model of the tree : it is printed as it is read
+    if print_cfg.format == OutputFormat::Text && tree_cfg.streams() {
+        let mut total = Summary::default();
+        for directory in bases {
+            total.add(print_stream(
+                outfile,
+                directory,
+                tree_cfg,
+                print_cfg,
+                resulting_canonical,
+                resulting_full_rel_path,
+                &mut *warn,
+            )?);
+        }
+        if !print_cfg.noreport {
+            my_write(outfile, format!("\n{}", report_line(&total, print_cfg)))?;
+        }
+        outfile.flush()?;
+        summary.add(total);
+        return Ok(summary);
+    }
+
+    let trees = bases
+        .into_iter()
+        .map(|directory| walk(directory, tree_cfg))
+        .collect::<io::Result<Vec<_>>>()?;
+    for dir in trees.iter().filter_map(|tree| tree.contents()) {
+        summary.add(dir.summary());
+        for (path, err) in dir.errors() {
+            warn(path, err);
+        }
+    }
+    type Printer = fn(&mut dyn Write, &[FileTree], &PrintConfig, bool, bool) -> io::Result<()>;
+    let print: Printer = match print_cfg.format {
+        OutputFormat::Text => print_text,
+        OutputFormat::Json => print_json,
+        OutputFormat::Xml => print_xml,
+        OutputFormat::Html => print_html,
+    };
+    print(
         outfile,
-        &opt.directory,
-        "", //  &String::from("")
+        &trees,
+        print_cfg,
         resulting_canonical,
         resulting_full_rel_path,
-        opt,
     )?;
-    if opt.directory.is_dir() {
-        let mut dirs_visited = Vec::new();
-        // add it to visited dirs
-        if !opt.fast_rsc {
-            dirs_visited.push(fs::canonicalize(PathBuf::from(&opt.directory)).unwrap());
-        }
-        if opt.ladv {
-            let tmp_buf = fs::canonicalize(opt.directory.as_path()).unwrap();
-            let mut tmp_dir = tmp_buf.as_path();
-            while let Some(x) = tmp_dir.parent() {
-                dirs_visited.push(fs::canonicalize(PathBuf::from(&x)).unwrap());
-                tmp_dir = x;
-            }
+    outfile.flush()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_name;
+    use super::stringify_permissions;
+    use super::{S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFREG, S_IFSOCK};
+    use crate::treeconfig::NameStyle;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    fn render(name: &[u8], style: NameStyle) -> Vec<u8> {
+        render_name(OsStr::from_bytes(name), style)
+    }
+
+    #[test]
+    fn plain_names() {
+        for style in [NameStyle::Escaped, NameStyle::Question, NameStyle::Raw] {
+            assert_eq!(
+                render("héllo.txt".as_bytes(), style),
+                "héllo.txt".as_bytes()
+            );
         }
-        visit_dirs(
-            outfile,
-            &mut dirs_visited,
-            &opt.directory,
-            "", // &String::from("")
-            0,
-            opt,
-        )?;
-    } else {
-        my_write(
-            outfile,
-            &format!(
-                "{}{}[given base is not a directory]",
-                FINAL_CHILD, FINAL_ENTRY
-            ),
+        assert_eq!(render(b"plain", NameStyle::Quoted), b"\"plain\"");
+    }
+
+    #[test]
+    fn control_characters() {
+        let name = b"new\nline\x1b[31m";
+        assert_eq!(render(name, NameStyle::Escaped), b"new\\x0aline\\x1b[31m");
+        assert_eq!(render(name, NameStyle::Question), b"new?line?[31m");
+        assert_eq!(render(name, NameStyle::Raw), name);
+        assert_eq!(
+            render(name, NameStyle::Quoted),
+            b"\"new\\x0aline\\x1b[31m\""
         );
     }
-    Ok(())
+
+    #[test]
+    fn bidi_overrides() {
+        let name = "rtl\u{202e}txt.exe".as_bytes();
+        assert_eq!(
+            render(name, NameStyle::Escaped),
+            b"rtl\\xe2\\x80\\xaetxt.exe"
+        );
+        assert_eq!(render(name, NameStyle::Question), b"rtl?txt.exe");
+        assert_eq!(render(name, NameStyle::Raw), name);
+        assert_eq!(
+            render("\u{2066}x".as_bytes(), NameStyle::Escaped),
+            b"\\xe2\\x81\\xa6x"
+        );
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        let name = b"bad\xff\xfename";
+        assert_eq!(render(name, NameStyle::Escaped), b"bad\\xff\\xfename");
+        assert_eq!(render(name, NameStyle::Question), b"bad??name");
+        assert_eq!(render(name, NameStyle::Raw), name);
+        assert_eq!(render(name, NameStyle::Quoted), b"\"bad\\xff\\xfename\"");
+    }
+
+    #[test]
+    fn quotes_and_backslashes() {
+        let name = b"quo\"te\\s";
+        assert_eq!(render(name, NameStyle::Quoted), b"\"quo\\\"te\\\\s\"");
+        assert_eq!(render(name, NameStyle::Escaped), name);
+    }
+
+    #[test]
+    fn permissions() {
+        assert_eq!(stringify_permissions(S_IFREG | 0o644), "-rw-r--r--");
+        assert_eq!(stringify_permissions(S_IFDIR | 0o755), "drwxr-xr-x");
+        assert_eq!(stringify_permissions(S_IFLNK | 0o777), "lrwxrwxrwx");
+        assert_eq!(stringify_permissions(0o600), "?rw-------");
+    }
+
+    #[test]
+    fn special_bits() {
+        assert_eq!(stringify_permissions(S_IFREG | 0o4755), "-rwsr-xr-x");
+        assert_eq!(stringify_permissions(S_IFREG | 0o4644), "-rwSr--r--");
+        assert_eq!(stringify_permissions(S_IFREG | 0o2755), "-rwxr-sr-x");
+        assert_eq!(stringify_permissions(S_IFREG | 0o2744), "-rwxr-Sr--");
+        assert_eq!(stringify_permissions(S_IFDIR | 0o1777), "drwxrwxrwt");
+        assert_eq!(stringify_permissions(S_IFDIR | 0o1776), "drwxrwxrwT");
+        assert_eq!(stringify_permissions(S_IFREG | 0o7000), "---S--S--T");
+    }
+
+    #[test]
+    fn file_types() {
+        assert_eq!(stringify_permissions(S_IFBLK | 0o660), "brw-rw----");
+        assert_eq!(stringify_permissions(S_IFCHR | 0o666), "crw-rw-rw-");
+        assert_eq!(stringify_permissions(S_IFIFO | 0o644), "prw-r--r--");
+        assert_eq!(stringify_permissions(S_IFSOCK | 0o755), "srwxr-xr-x");
+    }
 }
@@ -0,0 +1,286 @@
+//  wildcard patterns for -P and -I, as in GNU tree :
+//      *       any number of characters, but not '/'
+//      **      any number of characters, '/' included ; "**/" also matches no directory at all
+//      ?       any single character
+//      [...]   any character in the set, ranges a-z allowed, [!...] or [^...] negates
+//      |       alternation : "*.rs|Cargo.toml"
+//      \c      the character c, taken literally
+//  alternatives containing '/' are matched against the path relative to the root,
+//  all the others against the file name only
+
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+enum Token {
+    Char(char),
+    AnyChar,
+    Star,
+    DoubleStar,
+    /// "**/" : empty, or anything ending with '/'
+    AnyDirs,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Alternative {
+    tokens: Vec<Token>,
+    /// matched against the relative path rather than the file name
+    has_slash: bool,
+}
+
+impl Alternative {
+    fn new(tokens: Vec<Token>) -> Self {
+        let has_slash = tokens
+            .iter()
+            .any(|token| matches!(token, Token::Char('/') | Token::AnyDirs));
+        Alternative { tokens, has_slash }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    alternatives: Vec<Alternative>,
+    ignore_case: bool,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str, ignore_case: bool) -> Self {
+        let chars: Vec<char> = if ignore_case {
+            pattern.to_lowercase().chars().collect()
+        } else {
+            pattern.chars().collect()
+        };
+        let mut alternatives = Vec::new();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '|' => alternatives.push(Alternative::new(std::mem::take(&mut tokens))),
+                '?' => tokens.push(Token::AnyChar),
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    i += 1;
+                    if chars.get(i + 1) == Some(&'/') {
+                        i += 1;
+                        tokens.push(Token::AnyDirs);
+                    } else {
+                        tokens.push(Token::DoubleStar);
+                    }
+                }
+                '*' => tokens.push(Token::Star),
+                '[' => match parse_class(&chars, i) {
+                    Some((class, end)) => {
+                        tokens.push(class);
+                        i = end;
+                    }
+                    None => tokens.push(Token::Char('[')),
+                },
+                '\\' if i + 1 < chars.len() => {
+                    i += 1;
+                    tokens.push(Token::Char(chars[i]));
+                }
+                c => tokens.push(Token::Char(c)),
+            }
+            i += 1;
+        }
+        alternatives.push(Alternative::new(tokens));
+        Pattern {
+            alternatives,
+            ignore_case,
+        }
+    }
+
+    /// matches "text" as a whole
+    pub fn matches(&self, text: &str) -> bool {
+        let chars = self.chars(text);
+        self.alternatives
+            .iter()
+            .any(|alternative| match_tokens(&alternative.tokens, &chars))
+    }
+
+    /// matches the entry at "rel_path", relative to the root of the tree
+    pub fn matches_path(&self, rel_path: &Path) -> bool {
+        let name = match rel_path.file_name() {
+            Some(name) => self.chars(&name.to_string_lossy()),
+            None => return false,
+        };
+        let components: Vec<_> = rel_path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        let path = self.chars(&components.join("/"));
+        self.alternatives.iter().any(|alternative| {
+            let text = if alternative.has_slash { &path } else { &name };
+            match_tokens(&alternative.tokens, text)
+        })
+    }
+
+    fn chars(&self, text: &str) -> Vec<char> {
+        if self.ignore_case {
+            text.to_lowercase().chars().collect()
+        } else {
+            text.chars().collect()
+        }
+    }
+}
+
+// parse the class starting at chars[start] == '[', returns the token and the index of its ']'
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let first = i;
+    while i < chars.len() {
+        // a ']' right after the opening bracket is taken literally
+        if chars[i] == ']' && i > first {
+            return Some((Token::Class { negated, ranges }, i));
+        }
+        let low = chars[i];
+        if chars.get(i + 1) == Some(&'-') && i + 2 < chars.len() && chars[i + 2] != ']' {
+            ranges.push((low, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((low, low));
+            i += 1;
+        }
+    }
+    None
+}
+
+// whether "tokens" match all of "text"
+// each (token, text position) pair is tried at most once, which keeps "*a*a*a*b" linear
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let mut memo = vec![None; (tokens.len() + 1) * (text.len() + 1)];
+    match_from(tokens, text, 0, 0, &mut memo)
+}
+
+fn match_from(
+    tokens: &[Token],
+    text: &[char],
+    t: usize,
+    i: usize,
+    memo: &mut [Option<bool>],
+) -> bool {
+    let key = t * (text.len() + 1) + i;
+    if let Some(matched) = memo[key] {
+        return matched;
+    }
+    let matched = match tokens.get(t) {
+        None => i == text.len(),
+        Some(Token::Char(c)) => {
+            text.get(i) == Some(c) && match_from(tokens, text, t + 1, i + 1, memo)
+        }
+        Some(Token::AnyChar) => {
+            i < text.len() && text[i] != '/' && match_from(tokens, text, t + 1, i + 1, memo)
+        }
+        Some(Token::Class { negated, ranges }) => {
+            i < text.len()
+                && text[i] != '/'
+                && ranges.iter().any(|&(l, h)| l <= text[i] && text[i] <= h) != *negated
+                && match_from(tokens, text, t + 1, i + 1, memo)
+        }
+        // nothing, or one more character other than '/'
+        Some(Token::Star) => {
+            match_from(tokens, text, t + 1, i, memo)
+                || (i < text.len() && text[i] != '/' && match_from(tokens, text, t, i + 1, memo))
+        }
+        Some(Token::DoubleStar) => {
+            match_from(tokens, text, t + 1, i, memo)
+                || (i < text.len() && match_from(tokens, text, t, i + 1, memo))
+        }
+        // nothing, or directories up to the next '/'
+        Some(Token::AnyDirs) => {
+            match_from(tokens, text, t + 1, i, memo)
+                || (i..text.len())
+                    .filter(|&j| text[j] == '/')
+                    .any(|j| match_from(tokens, text, t + 1, j + 1, memo))
+        }
+    };
+    memo[key] = Some(matched);
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+    use std::path::Path;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern, false).matches(text)
+    }
+
+    #[test]
+    fn stars() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("**.rs", "src/main.rs"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "a/c"));
+    }
+
+    #[test]
+    fn any_dirs() {
+        assert!(matches("**/x.rs", "x.rs"));
+        assert!(matches("**/x.rs", "a/x.rs"));
+        assert!(matches("**/x.rs", "a/b/x.rs"));
+        assert!(!matches("**/x.rs", "ax.rs"));
+        assert!(matches("a/**/y.txt", "a/y.txt"));
+        assert!(matches("a/**/y.txt", "a/b/c/y.txt"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[abc].rs", "b.rs"));
+        assert!(!matches("[abc].rs", "d.rs"));
+        assert!(matches("f[0-9]", "f7"));
+        assert!(matches("f[!0-9]", "fx"));
+        assert!(!matches("f[^0-9]", "f7"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[abc", "[abc"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches("\\*.rs", "*.rs"));
+        assert!(!matches("\\*.rs", "main.rs"));
+        assert!(matches("a\\|b", "a|b"));
+        assert!(matches("\\[x]", "[x]"));
+    }
+
+    #[test]
+    fn alternation() {
+        assert!(matches("*.rs|Cargo.toml", "Cargo.toml"));
+        assert!(matches("*.rs|Cargo.toml", "lib.rs"));
+        assert!(!matches("*.rs|Cargo.toml", "Cargo.lock"));
+        assert!(matches("|x", ""));
+    }
+
+    #[test]
+    fn ignore_case() {
+        assert!(Pattern::new("*.RS", true).matches("main.rs"));
+        assert!(!Pattern::new("*.RS", false).matches("main.rs"));
+    }
+
+    #[test]
+    fn slash_per_alternative() {
+        let pattern = Pattern::new("a/*.rs|Cargo.toml", false);
+        assert!(pattern.matches_path(Path::new("a/x.rs")));
+        assert!(!pattern.matches_path(Path::new("b/a/x.rs")));
+        assert!(pattern.matches_path(Path::new("c/Cargo.toml")));
+        assert!(!pattern.matches_path(Path::new("x.rs")));
+    }
+
+    #[test]
+    fn no_exponential_backtracking() {
+        let text = "a".repeat(200);
+        assert!(!matches("*a*a*a*a*a*a*a*a*a*a*b", &text));
+        assert!(!matches("**a**a**a**a**a**a**a**a**b", &text));
+        assert!(matches("*a*a*a*a*a*a*a*a*a*a", &text));
+    }
+}
@@ -0,0 +1,308 @@
+//  parallel reading of directories, for --threads
+//  the walker stays sequential and keeps deciding what is listed, and in which order :
+//  the pool only reads directories ahead of it, so the output is the same as without threads.
+//  a work-stealing pool : the walker queues the subdirectories it is about to descend into,
+//  once its own filters have kept them, in a queue shared by the workers. each worker queues
+//  the subdirectories of what it reads in its own deque, and takes from its back, last in
+//  first out as the walk is depth first ; idle workers steal from the front of the others.
+//  workers only read ahead what the walker is sure to read as well : nothing excluded, over
+//  --filelimit, beyond -L or on another filesystem with -x, and nothing at all with
+//  --gitignore, whose rules only the walker knows. the walker takes the listings it needs,
+//  reading them itself if no worker has started on them yet.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::treeconfig::TreeConfig;
+use crate::treefile::read_entries;
+use crate::treefile::root_dev;
+use crate::treefile::FileTree;
+
+/// listings kept ahead of the walker, beyond which workers stop queuing subdirectories
+const AHEAD_LIMIT: usize = 4096;
+
+enum Slot {
+    Queued,
+    Running,
+    Done(io::Result<Vec<FileTree>>),
+}
+
+/// a directory to read, at "depth" below the root
+struct Job {
+    path: PathBuf,
+    depth: usize,
+}
+
+struct State {
+    /// jobs in all the deques, not yet claimed by a worker
+    queued: usize,
+    /// the walk is over, the workers stop
+    finished: bool,
+}
+
+pub(crate) struct Prefetcher<'a> {
+    cfg: &'a TreeConfig,
+    root: &'a Path,
+    /// device of the root, with -x
+    root_dev: Option<u64>,
+    /// jobs queued by the walker, the next one at the back
+    injector: Mutex<VecDeque<Job>>,
+    /// jobs queued by each worker, its own next one at the back, the next to steal at the front
+    locals: Vec<Mutex<VecDeque<Job>>>,
+    state: Mutex<State>,
+    /// a job was queued, or the walk is over
+    work: Condvar,
+    slots: Mutex<HashMap<PathBuf, Slot>>,
+    /// a slot became Done
+    ready: Condvar,
+}
+
+impl<'a> Prefetcher<'a> {
+    pub(crate) fn new(cfg: &'a TreeConfig, root: &'a Path, workers: usize) -> Self {
+        Prefetcher {
+            cfg,
+            root,
+            root_dev: root_dev(root, cfg),
+            injector: Mutex::new(VecDeque::new()),
+            locals: (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            state: Mutex::new(State {
+                queued: 0,
+                finished: false,
+            }),
+            work: Condvar::new(),
+            slots: Mutex::new(HashMap::new()),
+            ready: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn workers(&self) -> usize {
+        self.locals.len()
+    }
+
+    // queue the directories at "depth" the walk is going to descend into, in the order it will
+    pub(crate) fn prefetch(&self, paths: Vec<PathBuf>, depth: usize) {
+        self.push(&self.injector, paths, depth);
+    }
+
+    // queue the "paths" without a slot yet in "deque", the first one to be taken first
+    fn push(&self, deque: &Mutex<VecDeque<Job>>, paths: Vec<PathBuf>, depth: usize) {
+        let paths: Vec<PathBuf> = {
+            let mut slots = self.slots.lock().unwrap();
+            let mut queued = Vec::new();
+            for path in paths {
+                if !slots.contains_key(&path) {
+                    slots.insert(path.clone(), Slot::Queued);
+                    queued.push(path);
+                }
+            }
+            queued
+        };
+        if paths.is_empty() {
+            return;
+        }
+        let count = paths.len();
+        deque
+            .lock()
+            .unwrap()
+            .extend(paths.into_iter().rev().map(|path| Job { path, depth }));
+        let mut state = self.state.lock().unwrap();
+        if state.finished {
+            return;
+        }
+        state.queued += count;
+        drop(state);
+        self.work.notify_all();
+    }
+
+    // entries of the directory at "path"
+    pub(crate) fn take(&self, path: &Path) -> io::Result<Vec<FileTree>> {
+        let mut slots = self.slots.lock().unwrap();
+        loop {
+            match slots.remove(path) {
+                Some(Slot::Done(entries)) => return entries,
+                Some(Slot::Running) => {
+                    slots.insert(path.to_path_buf(), Slot::Running);
+                    slots = self.ready.wait(slots).unwrap();
+                }
+                // not started yet, or never queued (e.g. the target of a symlink) : read it here,
+                // a worker popping it later finds no Queued slot and skips it
+                Some(Slot::Queued) | None => {
+                    drop(slots);
+                    return read_entries(path, self.cfg);
+                }
+            }
+        }
+    }
+
+    // stop the workers once the walk is over, and drop what was read ahead for nothing
+    pub(crate) fn finish(&self) {
+        self.state.lock().unwrap().finished = true;
+        self.work.notify_all();
+        self.injector.lock().unwrap().clear();
+        for local in &self.locals {
+            local.lock().unwrap().clear();
+        }
+        self.slots.lock().unwrap().clear();
+    }
+
+    // loop of the worker number "index", until the walk is over
+    pub(crate) fn run_worker(&self, index: usize) {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                while !state.finished && state.queued == 0 {
+                    state = self.work.wait(state).unwrap();
+                }
+                if state.finished {
+                    return;
+                }
+                // a job is claimed : one is in some deque, and stays there for this worker
+                state.queued -= 1;
+            }
+            let job = loop {
+                if let Some(job) = self.pop(index) {
+                    break job;
+                }
+                // finish() cleared the deques meanwhile
+                if self.state.lock().unwrap().finished {
+                    return;
+                }
+                thread::yield_now();
+            };
+            self.read(index, job);
+        }
+    }
+
+    // next job of the worker number "index" : its own last one, the walker's, or a stolen one
+    fn pop(&self, index: usize) -> Option<Job> {
+        if let Some(job) = self.locals[index].lock().unwrap().pop_back() {
+            return Some(job);
+        }
+        if let Some(job) = self.injector.lock().unwrap().pop_back() {
+            return Some(job);
+        }
+        let workers = self.locals.len();
+        (1..workers)
+            .map(|offset| (index + offset) % workers)
+            .find_map(|victim| self.locals[victim].lock().unwrap().pop_front())
+    }
+
+    fn read(&self, index: usize, job: Job) {
+        {
+            let mut slots = self.slots.lock().unwrap();
+            match slots.get_mut(&job.path) {
+                Some(slot @ Slot::Queued) => *slot = Slot::Running,
+                // already taken by the walker
+                _ => return,
+            }
+        }
+        let entries = read_entries(&job.path, self.cfg);
+        let sub_dirs = match &entries {
+            Ok(entries) => self.sub_dirs(entries, job.depth),
+            Err(_) => Vec::new(),
+        };
+        let mut slots = self.slots.lock().unwrap();
+        // a slot dropped by finish() meanwhile stays dropped
+        if let Some(slot @ Slot::Running) = slots.get_mut(&job.path) {
+            *slot = Slot::Done(entries);
+        }
+        let ahead = slots.len();
+        drop(slots);
+        self.ready.notify_all();
+        if ahead < AHEAD_LIMIT {
+            self.push(&self.locals[index], sub_dirs, job.depth + 1);
+        }
+    }
+
+    // the subdirectories among "entries" of a directory at "depth" that the walker will read too
+    // as the walker counts only the entries kept by -P, the count here is never below its own
+    fn sub_dirs(&self, entries: &[FileTree], depth: usize) -> Vec<PathBuf> {
+        let cfg = self.cfg;
+        if cfg.gitignore || (cfg.level != 0 && depth + 1 >= cfg.level) {
+            return Vec::new();
+        }
+        let excluded = |node: &FileTree| {
+            let rel_path = node.path().strip_prefix(self.root).unwrap_or(node.path());
+            cfg.exclude.iter().any(|p| p.matches_path(rel_path))
+        };
+        let kept: Vec<&FileTree> = entries.iter().filter(|node| !excluded(node)).collect();
+        if cfg.filelimit != 0 && kept.len() > cfg.filelimit {
+            return Vec::new();
+        }
+        kept.into_iter()
+            .filter_map(|node| match node {
+                FileTree::DirNode(sub_dir) => Some(sub_dir),
+                _ => None,
+            })
+            .filter(|sub_dir| match (self.root_dev, &sub_dir.metadata) {
+                (None, _) => true,
+                (Some(root_dev), Some(mtd)) => mtd.dev() == root_dev,
+                (Some(_), None) => false,
+            })
+            .map(|sub_dir| sub_dir.path.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::treeconfig::PrintConfig;
+    use crate::treeconfig::TreeConfig;
+    use crate::treelibs::run;
+    use std::fs;
+    use std::path::Path;
+    use std::path::PathBuf;
+
+    // a tree "width" directories wide and "depth" deep, each with a few files
+    fn make_tree(dir: &Path, width: usize, depth: usize) {
+        fs::create_dir_all(dir).unwrap();
+        for file in ["a.txt", "b.rs", ".hidden"] {
+            fs::write(dir.join(file), file).unwrap();
+        }
+        if depth > 0 {
+            for index in 0..width {
+                make_tree(&dir.join(format!("d{}", index)), width, depth - 1);
+            }
+        }
+    }
+
+    fn listing(root: &Path, cfg: TreeConfig) -> Vec<u8> {
+        let mut output = Vec::new();
+        run(
+            &mut output,
+            &[root.to_path_buf()],
+            &cfg,
+            &PrintConfig::new(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        output
+    }
+
+    #[test]
+    fn threads_list_the_same_tree() {
+        let root: PathBuf =
+            std::env::temp_dir().join(format!("treepool-test-{}", std::process::id()));
+        make_tree(&root, 4, 4);
+        let configs: [fn() -> TreeConfig; 4] = [
+            TreeConfig::new,
+            || TreeConfig::new().show_hidden(true).level(3),
+            || TreeConfig::new().filelimit(4),
+            || TreeConfig::new().exclude(crate::Pattern::new("d1", false)),
+        ];
+        for config in configs {
+            let expected = listing(&root, config());
+            for threads in [2, 4, 8] {
+                assert_eq!(listing(&root, config().threads(threads)), expected);
+            }
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
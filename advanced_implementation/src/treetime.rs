@@ -0,0 +1,87 @@
+//  dates of the files, for -D
+//  absolute dates are formatted by the C library strftime, in the local timezone :
+//  the same formats as "date +FORMAT" or GNU tree --timefmt
+
+use std::ffi::CString;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::treeconfig::PrintConfig;
+use crate::treeconfig::TimeField;
+
+// as GNU tree : "Jan  2 15:04"
+const DEFAULT_TIMEFMT: &str = "%b %e %H:%M";
+
+// date of "mtd" as configured by "cfg", "?" if the filesystem does not record it
+pub(crate) fn format_time(mtd: &Metadata, cfg: &PrintConfig) -> String {
+    let secs = match cfg.time_field {
+        TimeField::Mtime => mtd.mtime(),
+        TimeField::Ctime => mtd.ctime(),
+        TimeField::Atime => mtd.atime(),
+        TimeField::Btime => match mtd.created() {
+            Ok(time) => epoch_secs(time),
+            Err(_) => return "?".to_string(),
+        },
+    };
+    if cfg.relative_time {
+        relative(epoch_secs(SystemTime::now()) - secs)
+    } else if cfg.timefmt.is_empty() {
+        strftime(secs, DEFAULT_TIMEFMT)
+    } else {
+        strftime(secs, &cfg.timefmt)
+    }
+}
+
+fn epoch_secs(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+// "age" seconds as "3h ago", in the largest unit it holds at least once
+fn relative(age: i64) -> String {
+    const UNITS: [(i64, &str); 5] = [
+        (365 * 24 * 3600, "y"),
+        (24 * 3600, "d"),
+        (3600, "h"),
+        (60, "m"),
+        (1, "s"),
+    ];
+    let (length, unit) = UNITS
+        .iter()
+        .find(|(length, _)| age.abs() >= *length)
+        .copied()
+        .unwrap_or((1, "s"));
+    if age < 0 {
+        format!("in {}{}", -age / length, unit)
+    } else {
+        format!("{}{} ago", age / length, unit)
+    }
+}
+
+fn strftime(secs: i64, format: &str) -> String {
+    let time = secs as libc::time_t;
+    let format = match CString::new(format) {
+        Ok(format) => format,
+        Err(_) => return String::new(),
+    };
+    let mut buf = [0u8; 256];
+    // SAFETY: "tm" is filled by localtime_r before being read,
+    // strftime writes at most buf.len() bytes and returns how many it wrote
+    let len = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return secs.to_string();
+        }
+        libc::strftime(
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
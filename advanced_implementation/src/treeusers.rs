@@ -0,0 +1,45 @@
+//  names of users and groups, for -u and -g
+//  read once from /etc/passwd and /etc/group : ids found in neither are printed as numbers
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+
+static USERS: OnceLock<HashMap<u32, String>> = OnceLock::new();
+static GROUPS: OnceLock<HashMap<u32, String>> = OnceLock::new();
+
+// name of the user "uid", or the id itself
+pub(crate) fn user_name(uid: u32, numeric: bool) -> String {
+    name(&USERS, "/etc/passwd", uid, numeric)
+}
+
+// name of the group "gid", or the id itself
+pub(crate) fn group_name(gid: u32, numeric: bool) -> String {
+    name(&GROUPS, "/etc/group", gid, numeric)
+}
+
+fn name(names: &OnceLock<HashMap<u32, String>>, file: &str, id: u32, numeric: bool) -> String {
+    if numeric {
+        return id.to_string();
+    }
+    names
+        .get_or_init(|| read_names(file))
+        .get(&id)
+        .cloned()
+        .unwrap_or_else(|| id.to_string())
+}
+
+// "name:password:id:..." lines, the first name of an id wins
+fn read_names(file: &str) -> HashMap<u32, String> {
+    let mut names = HashMap::new();
+    let text = fs::read_to_string(file).unwrap_or_default();
+    for line in text.lines() {
+        let mut fields = line.split(':');
+        let name = fields.next();
+        let id = fields.nth(1).and_then(|id| id.parse::<u32>().ok());
+        if let (Some(name), Some(id)) = (name, id) {
+            names.entry(id).or_insert_with(|| name.to_string());
+        }
+    }
+    names
+}
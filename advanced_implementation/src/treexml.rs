@@ -0,0 +1,193 @@
+//  XML printer of the model, compatible with the layout of GNU tree -X,
+//  one element per base directory, then the report of all of them :
+//  <?xml version="1.0" encoding="UTF-8"?>
+//  <tree>
+//    <directory name=".">
+//      <file name="a.txt" size="12"/>
+//    </directory>
+//    <report>
+//      <directories>0</directories>
+//      <files>1</files>
+//    </report>
+//  </tree>
+
+use std::fmt::Write as _;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+
+use crate::treeconfig::PermsFormat;
+use crate::treeconfig::PrintConfig;
+use crate::treeconfig::SizeFormat;
+use crate::treefile::FileTree;
+use crate::treelibs::entry_text;
+use crate::treelibs::my_write;
+use crate::treelibs::stringify_permissions;
+use crate::treelibs::trees_summary;
+use crate::treetime::format_time;
+use crate::treeusers::group_name;
+use crate::treeusers::user_name;
+
+pub(crate) fn print_xml(
+    outfile: &mut dyn std::io::Write,
+    trees: &[FileTree],
+    cfg: &PrintConfig,
+    keep_canonical: bool, //  for the base only
+    full_rel_path: bool,  //  for the base only
+) -> io::Result<()> {
+    let mut text = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tree>\n");
+    for tree in trees {
+        let attributes = node_attributes(tree, cfg, keep_canonical, full_rel_path);
+        if tree.contents().is_none() {
+            let _ = writeln!(
+                text,
+                "  <{}{}>\n    <error>given base is not a directory</error>\n  </{}>",
+                element(tree),
+                attributes,
+                element(tree)
+            );
+        } else {
+            write_node(&mut text, tree, attributes, cfg, 1);
+        }
+    }
+    if !cfg.noreport {
+        let summary = trees_summary(trees, cfg);
+        text.push_str("  <report>\n");
+        if cfg.size != SizeFormat::None {
+            let _ = writeln!(text, "    <size>{}</size>", summary.bytes);
+        }
+        let _ = writeln!(
+            text,
+            "    <directories>{}</directories>\n    <files>{}</files>\n  </report>",
+            summary.directories, summary.files
+        );
+    }
+    text.push_str("</tree>");
+    my_write(outfile, &text)
+}
+
+fn element(node: &FileTree) -> &'static str {
+    match node {
+        FileTree::DirNode(_) => "directory",
+        FileTree::FileNode(_) => "file",
+        FileTree::LinkNode(_) => "link",
+    }
+}
+
+// "node" and all its contents, at indentation "level"
+fn write_node(
+    text: &mut String,
+    node: &FileTree,
+    attributes: String,
+    cfg: &PrintConfig,
+    level: usize,
+) {
+    let indent = "  ".repeat(level);
+    let mut children = String::new();
+    if let FileTree::LinkNode(link) = node {
+        if let Some(reason) = link.not_followed() {
+            let _ = writeln!(children, "{}  <error>{}</error>", indent, reason);
+        }
+    }
+    if let Some(dir) = node.contents() {
+        match dir.status.message() {
+            Some(message) => {
+                let _ = writeln!(children, "{}  <error>{}</error>", indent, message);
+            }
+            None => {
+                for entry in &dir.entries {
+                    let attributes =
+                        node_attributes(entry, cfg, cfg.keep_canonical, cfg.full_rel_path);
+                    write_node(&mut children, entry, attributes, cfg, level + 1);
+                }
+                if let Some(omitted) = &dir.omitted {
+                    let _ = writeln!(
+                        children,
+                        "{}  <omitted entries=\"{}\" size=\"{}\"/>",
+                        indent, omitted.entries, omitted.bytes
+                    );
+                }
+            }
+        }
+    }
+    if children.is_empty() {
+        let _ = writeln!(text, "{}<{}{}/>", indent, element(node), attributes);
+    } else {
+        let _ = write!(
+            text,
+            "{}<{}{}>\n{}{}</{}>\n",
+            indent,
+            element(node),
+            attributes,
+            children,
+            indent,
+            element(node)
+        );
+    }
+}
+
+// attributes of "node" itself, each one preceded by a space
+fn node_attributes(
+    node: &FileTree,
+    cfg: &PrintConfig,
+    keep_canonical: bool,
+    full_rel_path: bool,
+) -> String {
+    let (filename, symlink) = entry_text(node, keep_canonical, full_rel_path, cfg.names);
+    let mut attributes = format!(" name=\"{}\"", escape(&filename));
+    if let FileTree::LinkNode(_) = node {
+        let _ = write!(attributes, " target=\"{}\"", escape(&symlink));
+    }
+    if let Some(mtd) = node.metadata() {
+        let mode = mtd.permissions().mode();
+        match cfg.perms {
+            PermsFormat::Symbolic => {
+                let _ = write!(
+                    attributes,
+                    " mode=\"{:04o}\" prot=\"{}\"",
+                    mode & 0o7777,
+                    stringify_permissions(mode)
+                );
+            }
+            PermsFormat::Numeric => {
+                let _ = write!(attributes, " mode=\"{:04o}\"", mode & 0o7777);
+            }
+            PermsFormat::None => {}
+        }
+        if cfg.user {
+            let user = user_name(mtd.uid(), cfg.numeric_ids);
+            let _ = write!(attributes, " user=\"{}\"", escape(&user));
+        }
+        if cfg.group {
+            let group = group_name(mtd.gid(), cfg.numeric_ids);
+            let _ = write!(attributes, " group=\"{}\"", escape(&group));
+        }
+        if cfg.size != SizeFormat::None {
+            let _ = write!(attributes, " size=\"{}\"", node.size(cfg.size_kind));
+        }
+        if cfg.date {
+            let _ = write!(attributes, " time=\"{}\"", escape(&format_time(mtd, cfg)));
+        }
+    }
+    attributes
+}
+
+// XML escapes ; control characters not allowed by XML 1.0 are printed as '?'
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => {
+                let _ = write!(escaped, "&#{};", c as u32);
+            }
+            c if (c as u32) < 0x20 => escaped.push('?'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}